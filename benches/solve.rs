@@ -0,0 +1,105 @@
+// Times the solver on a batch of boards with every rule given. Run with `cargo bench`,
+// or `cargo bench --bench solve -- --save-baseline` to keep the times in BASELINE for
+// later runs to compare against.
+use std::collections::HashMap;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use skyscrapers::{Board, WhichRule};
+
+const PUZZLES: usize = 20;
+const BASELINE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/benches/solve.baseline");
+
+// A Latin square made from shuffled rows, cols and numbers of the cyclic one.
+fn latin_square(n: usize, seed: &mut u64) -> Vec<Vec<usize>> {
+    let shuffled = |seed: &mut u64| {
+        let mut order: Vec<usize> = (0..n).collect();
+        for i in (1..n).rev() {
+            *seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            order.swap(i, (*seed >> 33) as usize % (i + 1));
+        }
+        order
+    };
+    let (rows, cols, numbers) = (shuffled(seed), shuffled(seed), shuffled(seed));
+    (0..n)
+        .map(|row| {
+            (0..n)
+                .map(|col| numbers[(rows[row] + cols[col]) % n] + 1)
+                .collect()
+        })
+        .collect()
+}
+
+fn rules(lines: impl Iterator<Item = Vec<usize>>) -> String {
+    lines
+        .map(|line| {
+            let mut visible = 0;
+            let mut tallest = 0;
+            for height in line {
+                if height > tallest {
+                    tallest = height;
+                    visible += 1;
+                }
+            }
+            char::from_digit(visible, 10).unwrap()
+        })
+        .collect()
+}
+
+fn puzzle(n: usize, seed: &mut u64) -> Board {
+    let square = latin_square(n, seed);
+    let col = |c: usize| (0..n).map(|r| square[r][c]).collect::<Vec<usize>>();
+    let mut board = Board::new_board(n);
+    board.update_rule_x(&rules((0..n).map(col)), WhichRule::Up);
+    board.update_rule_x(
+        &rules((0..n).map(|c| col(c).into_iter().rev().collect())),
+        WhichRule::Down,
+    );
+    board.update_rule_x(&rules(square.iter().cloned()), WhichRule::Left);
+    board.update_rule_x(
+        &rules(square.iter().map(|row| row.iter().rev().copied().collect())),
+        WhichRule::Right,
+    );
+    board
+}
+
+// Lines of "n nanoseconds-per-board".
+fn read_baseline() -> HashMap<usize, Duration> {
+    let text = fs::read_to_string(BASELINE).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| {
+            let (n, nanos) = line.split_once(' ')?;
+            Some((n.parse().ok()?, Duration::from_nanos(nanos.parse().ok()?)))
+        })
+        .collect()
+}
+
+fn main() {
+    let save_baseline = std::env::args().any(|arg| arg == "--save-baseline");
+    let baseline = read_baseline();
+    let mut results = String::new();
+    for n in [5, 7, 9] {
+        let mut seed = n as u64;
+        let boards: Vec<Board> = (0..PUZZLES).map(|_| puzzle(n, &mut seed)).collect();
+        let start = Instant::now();
+        for board in &boards {
+            assert!(board.solve().is_some());
+        }
+        let per_board = start.elapsed() / PUZZLES as u32;
+        print!("solve {}x{}: {:?} per board", n, n, per_board);
+        if let Some(before) = baseline.get(&n) {
+            print!(
+                " (baseline {:?}, {:.2}x)",
+                before,
+                before.as_secs_f64() / per_board.as_secs_f64()
+            );
+        }
+        println!();
+        results += &format!("{} {}\n", n, per_board.as_nanos());
+    }
+    if save_baseline {
+        fs::write(BASELINE, results).expect("Failed to write the baseline");
+    }
+}
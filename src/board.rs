@@ -1,7 +1,42 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
 
+use crate::line::{get_line_position, Line, LineView};
+use crate::technique::{run_default_passes, Area, Deduction, Techniques};
 use crate::Cell;
 
+// The line searches memoise on masks of numbers, which the default SipHash is slow for.
+#[derive(Default)]
+struct MaskHasher(u64);
+
+impl Hasher for MaskHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+type MaskMap<K, V> = HashMap<K, V, BuildHasherDefault<MaskHasher>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum WhichRule {
     Up,
     Down,
@@ -9,82 +44,310 @@ pub enum WhichRule {
     Right,
 }
 
+impl WhichRule {
+    pub const ALL: [WhichRule; 4] = [
+        WhichRule::Up,
+        WhichRule::Down,
+        WhichRule::Left,
+        WhichRule::Right,
+    ];
+
+    pub fn opposite(&self) -> WhichRule {
+        match self {
+            WhichRule::Up => WhichRule::Down,
+            WhichRule::Down => WhichRule::Up,
+            WhichRule::Left => WhichRule::Right,
+            WhichRule::Right => WhichRule::Left,
+        }
+    }
+
+    // The kind of line the rule looks at.
+    pub fn line_name(&self) -> &'static str {
+        match self {
+            WhichRule::Up | WhichRule::Down => "col",
+            WhichRule::Left | WhichRule::Right => "row",
+        }
+    }
+}
+
+impl fmt::Display for WhichRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WhichRule::Up => write!(f, "top"),
+            WhichRule::Down => write!(f, "bottom"),
+            WhichRule::Left => write!(f, "left"),
+            WhichRule::Right => write!(f, "right"),
+        }
+    }
+}
+
+// Something that makes the board impossible to finish. Rows, cols and rule indexes are
+// 0-based like Board::cells, Display shows them 1-based like the commands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Contradiction {
+    EmptyCell {
+        row: usize,
+        col: usize,
+    },
+    Duplicate {
+        number: usize,
+        first: (usize, usize),
+        second: (usize, usize),
+    },
+    UnreachableRule {
+        which_rule: WhichRule,
+        index: usize,
+        rule: usize,
+    },
+}
+
+impl fmt::Display for Contradiction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Contradiction::EmptyCell { row, col } => {
+                write!(f, "cell {}{} has no numbers left", row + 1, col + 1)
+            }
+            Contradiction::Duplicate {
+                number,
+                first,
+                second,
+            } => write!(
+                f,
+                "{} is placed twice, in cells {}{} and {}{}",
+                number,
+                first.0 + 1,
+                first.1 + 1,
+                second.0 + 1,
+                second.1 + 1
+            ),
+            Contradiction::UnreachableRule {
+                which_rule,
+                index,
+                rule,
+            } => write!(
+                f,
+                "rule {} from the {} of {} {} can't be met anymore",
+                rule,
+                which_rule,
+                which_rule.line_name(),
+                index + 1
+            ),
+        }
+    }
+}
+
+// How many candidates each check removed over all the rounds of Board::complete.
 #[derive(Debug)]
+pub struct CompletionReport {
+    pub rounds: usize,
+    pub removed: Vec<(&'static str, usize)>,
+    pub contradictions: Vec<Contradiction>,
+}
+
+// k cells of a line that hold only k numbers between them, so no other cell of the line
+// can have those numbers. `removed` lists the (cell, number) pairs this took out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NakedSubset {
+    pub cells: Vec<(usize, usize)>,
+    pub numbers: Vec<usize>,
+    pub removed: Vec<((usize, usize), usize)>,
+}
+
+impl fmt::Display for NakedSubset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cells: Vec<String> = self
+            .cells
+            .iter()
+            .map(|(row, col)| format!("{}{}", row + 1, col + 1))
+            .collect();
+        let numbers: Vec<String> = self.numbers.iter().map(|n| n.to_string()).collect();
+        let removed: Vec<String> = self
+            .removed
+            .iter()
+            .map(|((row, col), number)| format!("{} from {}{}", number, row + 1, col + 1))
+            .collect();
+        write!(
+            f,
+            "cells {} only hold {}, removed {}",
+            cells.join(", "),
+            numbers.join(", "),
+            removed.join(", ")
+        )
+    }
+}
+
+// A number that can only go in k cols of k rows (or k rows of k cols) can't be anywhere
+// else in those cols, since the k rows already need them all. `removed` lists the cells it
+// was taken out of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fish {
+    pub number: usize,
+    pub in_rows: bool,
+    pub lines: Vec<usize>,
+    pub cover: Vec<usize>,
+    pub removed: Vec<(usize, usize)>,
+}
+
+impl Fish {
+    pub fn name(&self) -> &'static str {
+        get_fish_name(self.lines.len())
+    }
+}
+
+pub(crate) fn get_fish_name(size: usize) -> &'static str {
+    match size {
+        2 => "X-Wing",
+        3 => "Swordfish",
+        _ => "Jellyfish",
+    }
+}
+
+impl fmt::Display for Fish {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (lines, cover) = if self.in_rows {
+            ("rows", "cols")
+        } else {
+            ("cols", "rows")
+        };
+        let join = |indexes: &[usize]| {
+            let indexes: Vec<String> = indexes.iter().map(|i| (i + 1).to_string()).collect();
+            indexes.join(", ")
+        };
+        let removed: Vec<String> = self
+            .removed
+            .iter()
+            .map(|(row, col)| format!("{}{}", row + 1, col + 1))
+            .collect();
+        write!(
+            f,
+            "{}: {} only goes in {} {} of {} {}, removed from {}",
+            self.name(),
+            self.number,
+            cover,
+            join(&self.cover),
+            lines,
+            join(&self.lines),
+            removed.join(", ")
+        )
+    }
+}
+
+// Fish bigger than a Jellyfish aren't looked for.
+const MAX_FISH_SIZE: usize = 4;
+
+#[derive(Debug, Clone)]
 pub struct Board {
     number: usize,
     rules_up: Vec<usize>,
     rules_down: Vec<usize>,
     rules_left: Vec<usize>,
     rules_right: Vec<usize>,
-    cells: Vec<Vec<Cell>>, // first is horizontal second is vertical
+    cells: Vec<Vec<Cell>>,   // first is horizontal second is vertical
+    givens: Vec<Vec<usize>>, // numbers printed in the puzzle, 0 if none
+    max_naked_subset_size: usize,
+    max_hidden_subset_size: usize,
+    techniques: Techniques,
+    deductions: Vec<Deduction>,
+    hint: Option<Hint>,
+}
+
+// The step "hint" is giving away, for the cells it was worked out on, and how many stages
+// of it were shown.
+#[derive(Debug, Clone)]
+struct Hint {
+    cells: Vec<Vec<Cell>>,
+    step: Vec<Deduction>,
+    stage: usize,
 }
 
 impl Board {
+    pub const DEFAULT_MAX_SUBSET_SIZE: usize = 4;
+
     pub fn new_board(number: usize) -> Board {
         Board {
             number,
             cells: vec![vec![Cell::new_cell(number); number]; number],
+            givens: vec![vec![0; number]; number],
             rules_up: vec![0; number],
             rules_down: vec![0; number],
             rules_left: vec![0; number],
             rules_right: vec![0; number],
+            max_naked_subset_size: Board::DEFAULT_MAX_SUBSET_SIZE,
+            max_hidden_subset_size: Board::DEFAULT_MAX_SUBSET_SIZE,
+            techniques: Techniques::default(),
+            deductions: vec![],
+            hint: None,
         }
     }
 
     pub fn print_board(&self) {
+        print!("{}", self.render_board(true));
+    }
+
+    // The board as print_board shows it. Without candidates the cells that aren't solved
+    // are left blank, e.g. for printing a puzzle on paper.
+    pub fn render_board(&self, show_candidates: bool) -> String {
+        let mut board = String::new();
         let adj_spaces = self.number / 2;
         let spaces_left = " ".repeat(adj_spaces);
-        let spaces_right;
-        if self.number % 2 == 0 {
-            spaces_right = " ".repeat(adj_spaces - 1);
+        let spaces_right = if self.number.is_multiple_of(2) {
+            " ".repeat(adj_spaces - 1)
         } else {
-            spaces_right = spaces_left.clone();
-        }
-        // print rules top
-        print!("     "); // 4 spaces + one more space for first |
+            spaces_left.clone()
+        };
+        // rules top
+        board.push_str("     "); // 4 spaces + one more space for first |
         for ru in &self.rules_up {
-            print!("{}", spaces_left);
+            board.push_str(&spaces_left);
             if ru > &0 {
-                print!("{}", ru);
+                board.push_str(&ru.to_string());
             } else {
-                print!(" ");
+                board.push(' ');
             }
-            print!("{}", spaces_right);
-            print!(" "); // |
+            board.push_str(&spaces_right);
+            board.push(' '); // |
         }
-        println!("");
+        board.push('\n');
         for row in 0..self.number {
-            print!("  "); // 2 spaces
+            board.push_str("  "); // 2 spaces
             if self.rules_left[row] > 0 {
-                print!("{} ", self.rules_left[row]); // Number + one space
+                board.push_str(&format!("{} ", self.rules_left[row])); // Number + one space
             } else {
-                print!("  "); // no number + one space
+                board.push_str("  "); // no number + one space
             }
             for col in 0..self.number {
-                print!("|");
-                self.cells[row][col].print_cell();
+                board.push('|');
+                let cell = &self.cells[row][col];
+                if self.givens[row][col] > 0 {
+                    board.push_str(&cell.render_given_cell());
+                } else if show_candidates || cell.value().is_some() {
+                    board.push_str(&cell.render_cell());
+                } else {
+                    board.push_str(&" ".repeat(self.number));
+                }
             }
-            print!("|");
+            board.push('|');
             if self.rules_right[row] > 0 {
-                println!(" {}", self.rules_right[row]);
+                board.push_str(&format!(" {}\n", self.rules_right[row]));
             } else {
-                println!("  ");
+                board.push_str("  \n");
             }
         }
 
-        // print rules down
-        print!("     "); // One more space for first |
+        // rules down
+        board.push_str("     "); // One more space for first |
         for rd in &self.rules_down {
-            print!("{}", spaces_left);
+            board.push_str(&spaces_left);
             if rd > &0 {
-                print!("{}", rd);
+                board.push_str(&rd.to_string());
             } else {
-                print!(" ");
+                board.push(' ');
             }
-            print!("{}", spaces_right);
-            print!(" "); // |
+            board.push_str(&spaces_right);
+            board.push(' '); // |
         }
-        println!("");
+        board.push('\n');
+        board
     }
 
     pub fn update_rule_x(&mut self, args: &str, which_rule: WhichRule) {
@@ -106,6 +369,17 @@ impl Board {
             }
             index += 1;
         }
+        self.hint = None;
+    }
+
+    pub fn set_rule(&mut self, which_rule: WhichRule, index: usize, rule: usize) {
+        match which_rule {
+            WhichRule::Up => self.rules_up[index] = rule,
+            WhichRule::Down => self.rules_down[index] = rule,
+            WhichRule::Left => self.rules_left[index] = rule,
+            WhichRule::Right => self.rules_right[index] = rule,
+        }
+        self.hint = None;
     }
 
     pub fn restore_cell(&mut self, args: &str) {
@@ -114,8 +388,12 @@ impl Board {
                 if let Some(row_n) = row_c.to_digit(10) {
                     if let Some(col_c) = args.chars().nth(1) {
                         if let Some(col_n) = col_c.to_digit(10) {
-                            if row_n <= self.number as u32 && col_n <= self.number as u32 {
+                            if row_n <= self.number as u32
+                                && col_n <= self.number as u32
+                                && !self.is_given(row_n as usize - 1, col_n as usize - 1)
+                            {
                                 self.cells[row_n as usize - 1][col_n as usize - 1].restore();
+                                self.forget_deductions(row_n as usize - 1, col_n as usize - 1);
                             }
                         }
                     }
@@ -136,9 +414,15 @@ impl Board {
                                     && hor_line_i < 1 + self.number as u32
                                     && ver_line_i > 0
                                     && ver_line_i < 1 + self.number as u32
+                                    && !self
+                                        .is_given(hor_line_i as usize - 1, ver_line_i as usize - 1)
                                 {
                                     self.cells[hor_line_i as usize - 1][ver_line_i as usize - 1]
                                         .replace_cell_with_number(number_i as usize);
+                                    self.forget_deductions(
+                                        hor_line_i as usize - 1,
+                                        ver_line_i as usize - 1,
+                                    );
                                 }
                             }
                         }
@@ -160,9 +444,15 @@ impl Board {
                                     && hor_line_i < 1 + self.number as u32
                                     && ver_line_i > 0
                                     && ver_line_i < 1 + self.number as u32
+                                    && !self
+                                        .is_given(hor_line_i as usize - 1, ver_line_i as usize - 1)
                                 {
                                     self.cells[hor_line_i as usize - 1][ver_line_i as usize - 1]
                                         .remove_number(number_i as usize);
+                                    self.forget_deductions(
+                                        hor_line_i as usize - 1,
+                                        ver_line_i as usize - 1,
+                                    );
                                 }
                             }
                         }
@@ -172,191 +462,767 @@ impl Board {
         }
     }
 
-    pub fn check_all(&mut self) {
-        self.check_integrity(); // TODO:
-        self.check_line(WhichRule::Up);
-        self.check_line(WhichRule::Down);
-        self.check_line(WhichRule::Left);
-        self.check_line(WhichRule::Right);
-        self.check_unique_number_left_all_cells();
-        self.check_exclusive_numbers_all_cells();
-        self.check_compl_cell_excl_hor_and_ver();
-    }
-
-    fn check_integrity(&self) {
-        // let mut i = 0;
-        // for hor_cells in &self.cells.clone() {
-        //     let mut j = 0;
-        //     for cell in hor_cells {
-        //         if cell.numbers.len() == 1 {
-        //             let number_to_check = cell.numbers[0];
-        //             for inner_range_num in 0..self.number {
-        //                 let cell_row = &self.cells[inner_range_num][j];
-        //                 let cell_col = &self.cells[i][inner_range_num];
-        //                 // check if cell_row and cell_col don't have the number_to_check
-        //             }
-        //         }
-        //         j += 1;
-        //     }
-        //     i += 1;
-        // }
-    }
-
-    fn check_compl_cell_excl_hor_and_ver(&mut self) {
-        let mut i = 0;
-        for hor_cells in &self.cells.clone() {
-            let mut j = 0;
-            for cell in hor_cells {
-                if cell.numbers.len() == 1 {
-                    let number_to_erase = cell.numbers[0];
-                    for inner_range_num in 0..self.number {
-                        self.cells[inner_range_num][j].remove_number(number_to_erase);
-                        self.cells[i][inner_range_num].remove_number(number_to_erase);
-                    }
+    // Runs every check once and returns how many candidates each one removed. What they
+    // did goes to the deduction log. A board that is already broken is left as it is.
+    pub fn check_all(&mut self) -> Result<Vec<(&'static str, usize)>, Vec<Contradiction>> {
+        let contradictions = self.check_integrity();
+        if !contradictions.is_empty() {
+            return Err(contradictions);
+        }
+        let mut removed = vec![];
+        for (name, deductions) in self.run_checks() {
+            removed.push((
+                name,
+                deductions
+                    .iter()
+                    .filter(|deduction| !deduction.placed)
+                    .count(),
+            ));
+            self.deductions.extend(deductions);
+        }
+        Ok(removed)
+    }
+
+    // Makes only the first deduction the easiest technique finds and records it: one
+    // candidate taken out of one cell, and the number it leaves if only one is left.
+    // Returns nothing if no technique can make progress.
+    pub fn step(&mut self) -> Vec<Deduction> {
+        let step = self.next_step();
+        self.apply_step(&step);
+        step
+    }
+
+    // Makes everything the easiest technique finds about the first area it looks at (a
+    // line and its clues, a solved cell, a subset or a fish) at once. The grader counts
+    // one of these as a step.
+    pub(crate) fn step_area(&mut self) -> Vec<Deduction> {
+        let step = self.next_area();
+        self.apply_step(&step);
+        step
+    }
+
+    fn apply_step(&mut self, step: &[Deduction]) {
+        for deduction in step.iter().filter(|deduction| !deduction.placed) {
+            self.cells[deduction.row][deduction.col].remove_number(deduction.number);
+        }
+        self.deductions.extend(step.iter().copied());
+    }
+
+    // The deductions Board::step would make.
+    fn next_step(&self) -> Vec<Deduction> {
+        let first = match self.next_area().first() {
+            Some(&first) => first,
+            None => return vec![],
+        };
+        let mut step = vec![first];
+        let mut cell = self.cells[first.row][first.col];
+        cell.remove_number(first.number);
+        if let Some(number) = cell.value() {
+            step.push(Deduction {
+                number,
+                placed: true,
+                ..first
+            });
+        }
+        step
+    }
+
+    // The deductions Board::step_area would make: the first area found by the easiest
+    // technique that finds anything, trying them on a copy of the board.
+    fn next_area(&self) -> Vec<Deduction> {
+        let mut techniques = self.techniques.enabled();
+        techniques.sort_by_key(|technique| technique.difficulty());
+        for technique in techniques {
+            let deductions = technique.apply(&mut self.clone());
+            if let Some(first) = deductions.first() {
+                let area = first.area;
+                return deductions
+                    .into_iter()
+                    .take_while(|deduction| deduction.area == area)
+                    .collect();
+            }
+        }
+        vec![]
+    }
+
+    // Gives away the next step one stage more each call, without making it: 1 is where to
+    // look, 2 the technique and 3 the deductions. Starts over once the cells, rules or
+    // techniques change. None if no technique can make progress.
+    pub fn hint(&mut self) -> Option<(usize, Vec<Deduction>)> {
+        if !matches!(&self.hint, Some(hint) if hint.cells == self.cells) {
+            self.hint = Some(Hint {
+                cells: self.cells.clone(),
+                step: self.next_step(),
+                stage: 0,
+            });
+        }
+        let hint = self.hint.as_mut().unwrap();
+        if hint.step.is_empty() {
+            return None;
+        }
+        hint.stage = (hint.stage + 1).min(3);
+        Some((hint.stage, hint.step.clone()))
+    }
+
+    fn run_checks(&mut self) -> Vec<(&'static str, Vec<Deduction>)> {
+        self.techniques
+            .enabled()
+            .into_iter()
+            .map(|technique| (technique.name(), technique.apply(self)))
+            .collect()
+    }
+
+    pub fn techniques(&self) -> &Techniques {
+        &self.techniques
+    }
+
+    pub fn techniques_mut(&mut self) -> &mut Techniques {
+        self.hint = None;
+        &mut self.techniques
+    }
+
+    pub fn get_number(&self) -> usize {
+        self.number
+    }
+
+    pub fn get_cell(&self, row: usize, col: usize) -> Cell {
+        self.cells[row][col]
+    }
+
+    // Takes `number` out of a cell, unless it is the last one there. Returns true if it was
+    // taken out. This is how a Technique from outside this file changes the board.
+    pub fn remove_candidate(&mut self, row: usize, col: usize, number: usize) -> bool {
+        let old = self.cells[row][col];
+        self.cells[row][col].remove_number(number);
+        self.cells[row][col] != old
+    }
+
+    pub(crate) fn set_number(&mut self, row: usize, col: usize, number: usize) {
+        self.cells[row][col].replace_cell_with_number(number);
+    }
+
+    pub fn get_given(&self, row: usize, col: usize) -> usize {
+        self.givens[row][col]
+    }
+
+    fn is_given(&self, row: usize, col: usize) -> bool {
+        self.givens[row][col] > 0
+    }
+
+    // The puzzle itself: the rules and givens of the board with every other cell as it
+    // started, and nothing in the log.
+    pub fn to_puzzle(&self) -> Board {
+        let mut puzzle = Board {
+            cells: vec![vec![Cell::new_cell(self.number); self.number]; self.number],
+            deductions: vec![],
+            hint: None,
+            ..self.clone()
+        };
+        for row in 0..self.number {
+            for col in 0..self.number {
+                if self.is_given(row, col) {
+                    puzzle.cells[row][col].replace_cell_with_number(self.givens[row][col]);
                 }
-                j += 1;
             }
-            i += 1;
         }
+        puzzle
+    }
+
+    // Makes `number` part of the puzzle in a cell, so ok, remove and restore leave it alone.
+    // 0 takes the given out again and gives the cell all its numbers back.
+    pub fn set_given(&mut self, row: usize, col: usize, number: usize) {
+        if number > self.number {
+            return;
+        }
+        if number > 0 {
+            self.cells[row][col].replace_cell_with_number(number);
+        } else if self.is_given(row, col) {
+            self.cells[row][col].restore();
+        }
+        self.givens[row][col] = number;
+        self.forget_deductions(row, col);
+    }
+
+    // Everything check_all did so far, oldest first.
+    pub fn deductions(&self) -> &[Deduction] {
+        &self.deductions
+    }
+
+    // Why `number` is gone from a cell, or why it is the only number left there. None if
+    // the cell still has other numbers with it, or nothing recorded it.
+    pub fn get_deduction(&self, row: usize, col: usize, number: usize) -> Option<&Deduction> {
+        let cell = self.cells[row][col];
+        let placed = cell.value() == Some(number);
+        if !placed && cell.contains(number) {
+            return None;
+        }
+        self.deductions.iter().rev().find(|deduction| {
+            (
+                deduction.row,
+                deduction.col,
+                deduction.number,
+                deduction.placed,
+            ) == (row, col, number, placed)
+        })
+    }
+
+    // Adds what a pass run on its own did to the log, as if `technique` had done it.
+    fn log_deductions(&mut self, technique: &'static str, deductions: Vec<Deduction>) {
+        self.deductions
+            .extend(deductions.into_iter().map(|deduction| Deduction {
+                technique,
+                ..deduction
+            }));
+    }
+
+    // Changing a cell by hand makes what was recorded about it meaningless.
+    fn forget_deductions(&mut self, row: usize, col: usize) {
+        self.deductions
+            .retain(|deduction| (deduction.row, deduction.col) != (row, col));
     }
 
-    fn check_exclusive_numbers_all_cells(&mut self) {
-        // Do the cols.
-        for col in 0..self.number {
-            let mut cells: Vec<Cell> = vec![];
-            for row in 0..self.number {
-                cells.push(self.cells[row][col].clone());
+    // Repeats check_all until no cell changes, or until `depth` rounds have run.
+    pub fn complete(&mut self, depth: Option<usize>) -> CompletionReport {
+        let mut report = CompletionReport {
+            rounds: 0,
+            removed: self
+                .techniques
+                .enabled()
+                .iter()
+                .map(|technique| (technique.name(), 0))
+                .collect(),
+            contradictions: vec![],
+        };
+        while depth.is_none_or(|depth| report.rounds < depth) {
+            let before = self.cells.clone();
+            let removed = match self.check_all() {
+                Ok(removed) => removed,
+                Err(contradictions) => {
+                    report.contradictions = contradictions;
+                    break;
+                }
+            };
+            report.rounds += 1;
+            for (total, (_, count)) in report.removed.iter_mut().zip(removed) {
+                total.1 += count;
+            }
+            if before == self.cells {
+                break;
             }
-            check_exclusive_numbers(&mut cells);
-            for row in 0..self.number {
-                if self.cells[row][col] != cells[row] {
-                    self.cells[row][col] = cells[row].clone();
+        }
+        report
+    }
+
+    pub fn solve(&self) -> Option<Board> {
+        self.find_solutions(1).pop()
+    }
+
+    // Counts the solutions of the board, stopping once `limit` of them are found.
+    pub fn count_solutions(&self, limit: usize) -> usize {
+        self.find_solutions(limit).len()
+    }
+
+    // Returns up to `limit` different solutions of the board.
+    pub fn find_solutions(&self, limit: usize) -> Vec<Board> {
+        // The search copies the board a lot, so leave the log behind.
+        let mut board = self.clone();
+        board.deductions.clear();
+        let mut solutions = vec![];
+        board.search_solutions(limit, &mut solutions);
+        solutions
+    }
+
+    fn search_solutions(&self, limit: usize, solutions: &mut Vec<Board>) {
+        if solutions.len() >= limit {
+            return;
+        }
+        let mut board = self.clone();
+        if !board.propagate() {
+            return;
+        }
+        let (row, col) = match board.most_constrained_cell() {
+            Some(position) => position,
+            None => {
+                if board.is_solved() {
+                    solutions.push(board);
                 }
+                return;
             }
+        };
+        // Every guess stays in its branch (propagate fails if a cell changes to a number
+        // it didn't have), so no solution is found twice.
+        for number in board.cells[row][col].iter() {
+            let mut guess = board.clone();
+            guess.cells[row][col].replace_cell_with_number(number);
+            guess.search_solutions(limit, solutions);
         }
+    }
 
-        // // Do the rows.
+    // The cells where two boards differ, as (row, col) pairs.
+    pub fn differences(&self, other: &Board) -> Vec<(usize, usize)> {
+        let mut result = vec![];
         for row in 0..self.number {
-            let mut cells: Vec<Cell> = vec![];
-            for col in 0..self.number {
-                cells.push(self.cells[row][col].clone());
-            }
-            check_exclusive_numbers(&mut cells);
             for col in 0..self.number {
-                if self.cells[row][col] != cells[col] {
-                    self.cells[row][col] = cells[col].clone();
+                if self.cells[row][col] != other.cells[row][col] {
+                    result.push((row, col));
                 }
             }
         }
+        result
     }
 
-    fn check_unique_number_left_all_cells(&mut self) {
-        // Do the cols.
-        for col in 0..self.number {
-            let mut cells: Vec<Cell> = vec![];
-            for row in 0..self.number {
-                cells.push(self.cells[row][col].clone());
+    // Runs the checks until the cells stop changing. Returns false as soon as the board
+    // can't lead to a solution anymore. The techniques of the board aren't used, so what
+    // is enabled in the REPL doesn't change how boards are solved.
+    fn propagate(&mut self) -> bool {
+        loop {
+            let before = self.cells.clone();
+            run_default_passes(self);
+
+            // The checks overwrite a cell instead of failing when its line is already
+            // broken, so a cell that gained a number means there is a contradiction.
+            let cell_gained_number = before
+                .iter()
+                .flatten()
+                .zip(self.cells.iter().flatten())
+                .any(|(old, new)| !old.contains_cell(new));
+            if cell_gained_number {
+                return false;
             }
-            check_unique_number_left(&mut cells);
-            for row in 0..self.number {
-                if self.cells[row][col] != cells[row] {
-                    self.cells[row][col] = cells[row].clone();
-                }
+            // Looking for broken rules is slow, so only do it once nothing changes.
+            if before == self.cells {
+                return self.check_integrity().is_empty();
             }
         }
+    }
 
-        // // Do the rows.
+    fn most_constrained_cell(&self) -> Option<(usize, usize)> {
+        let mut result = None;
+        let mut fewest_numbers = usize::MAX;
         for row in 0..self.number {
-            let mut cells: Vec<Cell> = vec![];
             for col in 0..self.number {
-                cells.push(self.cells[row][col].clone());
+                let len = self.cells[row][col].count();
+                if len > 1 && len < fewest_numbers {
+                    fewest_numbers = len;
+                    result = Some((row, col));
+                }
             }
-            check_unique_number_left(&mut cells);
+        }
+        result
+    }
+
+    // True if every cell holds one number and nothing contradicts the rules.
+    pub fn is_solved(&self) -> bool {
+        self.cells
+            .iter()
+            .flatten()
+            .all(|cell| cell.value().is_some())
+            && self.check_integrity().is_empty()
+    }
+
+    pub fn check_integrity(&self) -> Vec<Contradiction> {
+        let mut contradictions = vec![];
+        for row in 0..self.number {
             for col in 0..self.number {
-                if self.cells[row][col] != cells[col] {
-                    self.cells[row][col] = cells[col].clone();
+                if self.cells[row][col].is_empty() {
+                    contradictions.push(Contradiction::EmptyCell { row, col });
                 }
             }
         }
-    }
 
-    fn check_line(&mut self, which_rule: WhichRule) {
-        match which_rule {
-            WhichRule::Up => {
-                for col in 0..self.number {
-                    let rule = self.rules_up[col];
-                    if rule > 0 {
-                        let mut cells: Vec<Cell> = vec![];
-                        for row in 0..self.number {
-                            cells.push(self.cells[row][col].clone());
-                        }
-                        check_cell_line_by_rule(rule, &mut cells);
-                        for row in 0..self.number {
-                            if self.cells[row][col] != cells[row] {
-                                self.cells[row][col] = cells[row].clone();
-                            }
+        for i in 0..self.number {
+            let row_positions: Vec<(usize, usize)> = (0..self.number).map(|j| (i, j)).collect();
+            let col_positions: Vec<(usize, usize)> = (0..self.number).map(|j| (j, i)).collect();
+            for positions in [row_positions, col_positions] {
+                for (a, &first) in positions.iter().enumerate() {
+                    for &second in &positions[a + 1..] {
+                        let number = self.cells[first.0][first.1].value();
+                        if let Some(number) = number.filter(|&number| {
+                            self.cells[second.0][second.1].value() == Some(number)
+                        }) {
+                            contradictions.push(Contradiction::Duplicate {
+                                number,
+                                first,
+                                second,
+                            });
                         }
                     }
                 }
             }
-            WhichRule::Down => {
-                for col in 0..self.number {
-                    let rule = self.rules_down[col];
-                    if rule > 0 {
-                        let mut cells: Vec<Cell> = vec![];
-                        for row in (0..self.number).rev() {
-                            cells.push(self.cells[row][col].clone());
-                        }
-                        check_cell_line_by_rule(rule, &mut cells);
-                        for row in (0..self.number).rev() {
-                            let reverse_row = self.number - row - 1;
-                            if self.cells[row][col] != cells[reverse_row] {
-                                self.cells[row][col] = cells[reverse_row].clone();
-                            }
+        }
+
+        for which_rule in WhichRule::ALL {
+            for index in 0..self.number {
+                let rule = self.get_rule(which_rule, index);
+                if rule == 0 {
+                    continue;
+                }
+                let cells = self.get_line_cells(which_rule, index);
+                if !get_visibility_bounds(&cells, &mut MaskMap::default())
+                    .is_some_and(|(min, max)| (min..=max).contains(&rule))
+                {
+                    contradictions.push(Contradiction::UnreachableRule {
+                        which_rule,
+                        index,
+                        rule,
+                    });
+                }
+            }
+        }
+        contradictions
+    }
+
+    pub fn get_rule(&self, which_rule: WhichRule, index: usize) -> usize {
+        match which_rule {
+            WhichRule::Up => self.rules_up[index],
+            WhichRule::Down => self.rules_down[index],
+            WhichRule::Left => self.rules_left[index],
+            WhichRule::Right => self.rules_right[index],
+        }
+    }
+
+    // The cells of the line a rule looks at, starting from the cell next to the rule.
+    fn get_line_cells(&self, which_rule: WhichRule, index: usize) -> Vec<Cell> {
+        (0..self.number)
+            .map(|i| get_line_position(self.number, which_rule, index, i))
+            .map(|(row, col)| self.cells[row][col])
+            .collect()
+    }
+
+    fn get_line_view(&mut self, which_rule: WhichRule, index: usize) -> LineView<'_> {
+        LineView::new(&mut self.cells, which_rule, index)
+    }
+
+    // Records what changed in a cell since it was `old` as deductions based on `area`.
+    // Technique::apply fills in the technique.
+    fn record_deductions(
+        &self,
+        area: Area,
+        position: (usize, usize),
+        old: Cell,
+        deductions: &mut Vec<Deduction>,
+    ) {
+        let new = self.cells[position.0][position.1];
+        push_deductions(area, position, old, new, deductions);
+    }
+
+    // Runs a line check on the line of `which_rule` and records what it did, with the
+    // clues (near, far) it used.
+    fn check_line_view<F>(
+        &mut self,
+        which_rule: WhichRule,
+        index: usize,
+        (near_rule, far_rule): (usize, usize),
+        check: F,
+    ) -> Vec<Deduction>
+    where
+        F: FnOnce(&mut LineView),
+    {
+        let before = self.get_line_cells(which_rule, index);
+        check(&mut self.get_line_view(which_rule, index));
+        let area = Area::Line {
+            which_rule,
+            index,
+            near_rule,
+            far_rule,
+        };
+        let mut result = vec![];
+        for (i, old) in before.into_iter().enumerate() {
+            let position = get_line_position(self.number, which_rule, index, i);
+            self.record_deductions(area, position, old, &mut result);
+        }
+        result
+    }
+
+    pub(crate) fn check_compl_cell_excl_hor_and_ver(&mut self) -> Vec<Deduction> {
+        let mut result = vec![];
+        for (i, hor_cells) in self.cells.clone().iter().enumerate() {
+            for (j, cell) in hor_cells.iter().enumerate() {
+                if let Some(number_to_erase) = cell.value() {
+                    let area = Area::Cell {
+                        row: i,
+                        col: j,
+                        number: number_to_erase,
+                    };
+                    for inner_range_num in 0..self.number {
+                        for (row, col) in [(inner_range_num, j), (i, inner_range_num)] {
+                            let old = self.cells[row][col];
+                            self.cells[row][col].remove_number(number_to_erase);
+                            self.record_deductions(area, (row, col), old, &mut result);
                         }
                     }
                 }
             }
-            WhichRule::Left => {
-                for row in 0..self.number {
-                    let rule = self.rules_left[row];
-                    if rule > 0 {
-                        let mut cells: Vec<Cell> = vec![];
-                        for col in 0..self.number {
-                            cells.push(self.cells[row][col].clone());
-                        }
-                        check_cell_line_by_rule(rule, &mut cells);
-                        for col in 0..self.number {
-                            if self.cells[row][col] != cells[col] {
-                                self.cells[row][col] = cells[col].clone();
-                            }
-                        }
+        }
+        result
+    }
+
+    pub fn max_naked_subset_size(&self) -> usize {
+        self.max_naked_subset_size
+    }
+
+    // The naked subsets pass only looks at groups of up to this many cells, so below 2 it
+    // finds nothing.
+    pub fn set_max_naked_subset_size(&mut self, size: usize) {
+        self.max_naked_subset_size = size;
+    }
+
+    pub fn max_hidden_subset_size(&self) -> usize {
+        self.max_hidden_subset_size
+    }
+
+    // The hidden subsets pass only looks at groups of up to this many numbers.
+    pub fn set_max_hidden_subset_size(&mut self, size: usize) {
+        self.max_hidden_subset_size = size;
+    }
+
+    // Looks for naked subsets in every row and col and returns the ones that removed
+    // something.
+    pub fn remove_naked_subsets(&mut self) -> Vec<NakedSubset> {
+        let mut deductions = vec![];
+        let subsets = self.find_naked_subsets(&mut deductions);
+        self.log_deductions("naked subsets", deductions);
+        subsets
+    }
+
+    fn find_naked_subsets(&mut self, deductions: &mut Vec<Deduction>) -> Vec<NakedSubset> {
+        let max_size = self.max_naked_subset_size;
+        let mut result = vec![];
+        for (which_rule, in_rows) in [(WhichRule::Up, false), (WhichRule::Left, true)] {
+            // Where a cell is on its line
+            let i_of = |(row, col): (usize, usize)| if in_rows { col } else { row };
+            for index in 0..self.number {
+                let mut cells = self.get_line_cells(which_rule, index);
+                let subsets =
+                    check_naked_subsets(&mut self.get_line_view(which_rule, index), max_size);
+                // Replays what each subset removed, so every deduction has its own subset.
+                for subset in &subsets {
+                    let area = Area::Subset {
+                        in_rows,
+                        line: index,
+                        cells: subset
+                            .cells
+                            .iter()
+                            .fold(0, |mask, &position| mask | 1 << i_of(position)),
+                        numbers: subset
+                            .numbers
+                            .iter()
+                            .fold(0, |mask, number| mask | 1 << (number - 1)),
+                    };
+                    for &(position, number) in &subset.removed {
+                        let old = cells[i_of(position)];
+                        cells[i_of(position)].remove_number(number);
+                        push_deductions(area, position, old, cells[i_of(position)], deductions);
                     }
                 }
+                result.extend(subsets);
             }
-            WhichRule::Right => {
-                for row in 0..self.number {
-                    let rule = self.rules_right[row];
-                    if rule > 0 {
-                        let mut cells: Vec<Cell> = vec![];
-                        for col in (0..self.number).rev() {
-                            cells.push(self.cells[row][col].clone());
+        }
+        result
+    }
+
+    // Looks for X-Wings, Swordfish and Jellyfish of every number, first with rows as the
+    // lines and cols as the cover and then the other way around.
+    pub fn remove_fish(&mut self) -> Vec<Fish> {
+        let mut deductions = vec![];
+        let fish = self.find_fish(&mut deductions);
+        self.log_deductions("fish", deductions);
+        fish
+    }
+
+    fn find_fish(&mut self, deductions: &mut Vec<Deduction>) -> Vec<Fish> {
+        let mut result = vec![];
+        for (which_rule, in_rows) in [(WhichRule::Left, true), (WhichRule::Up, false)] {
+            for number in 1..=self.number {
+                for size in 2..=MAX_FISH_SIZE.min(self.number.saturating_sub(1)) {
+                    let places = self.get_fish_places(which_rule, number);
+                    let open: Vec<usize> = (0..self.number)
+                        .filter(|&line| (2..=size).contains(&(places[line].count_ones() as usize)))
+                        .collect();
+                    let mut subsets = vec![];
+                    find_subsets(&places, &open, size, &mut vec![], 0, &mut subsets);
+                    for lines in subsets {
+                        let places = self.get_fish_places(which_rule, number);
+                        let cover = lines.iter().fold(0, |mask, &line| mask | places[line]);
+                        if cover.count_ones() as usize != size {
+                            continue;
                         }
-                        check_cell_line_by_rule(rule, &mut cells);
-                        for col in (0..self.number).rev() {
-                            let reverse_col = self.number - col - 1;
-                            if self.cells[row][col] != cells[reverse_col] {
-                                self.cells[row][col] = cells[reverse_col].clone();
+                        let cover: Vec<usize> =
+                            (0..self.number).filter(|&i| cover & 1 << i != 0).collect();
+                        let area = Area::Fish {
+                            number,
+                            in_rows,
+                            lines: lines.iter().fold(0, |mask, line| mask | 1 << line),
+                        };
+                        let mut removed = vec![];
+                        for line in (0..self.number).filter(|line| !lines.contains(line)) {
+                            for &i in &cover {
+                                let (row, col) =
+                                    get_line_position(self.number, which_rule, line, i);
+                                let old = self.cells[row][col];
+                                if old.contains(number) && old.count() > 1 {
+                                    self.cells[row][col].remove_number(number);
+                                    self.record_deductions(area, (row, col), old, deductions);
+                                    removed.push((row, col));
+                                }
                             }
                         }
+                        if !removed.is_empty() {
+                            result.push(Fish {
+                                number,
+                                in_rows,
+                                lines,
+                                cover,
+                                removed,
+                            });
+                        }
                     }
                 }
             }
         }
+        result
+    }
+
+    // For every row (Left) or col (Up), the cells `number` can still go in, with bit i set
+    // for the i-th cell of the line.
+    fn get_fish_places(&self, which_rule: WhichRule, number: usize) -> Vec<u32> {
+        (0..self.number)
+            .map(|line| {
+                (0..self.number)
+                    .filter(|&i| {
+                        let (row, col) = get_line_position(self.number, which_rule, line, i);
+                        self.cells[row][col].contains(number)
+                    })
+                    .fold(0, |mask, i| mask | 1 << i)
+            })
+            .collect()
+    }
+
+    pub(crate) fn check_naked_subsets_all_cells(&mut self) -> Vec<Deduction> {
+        let mut result = vec![];
+        self.find_naked_subsets(&mut result);
+        result
+    }
+
+    pub(crate) fn check_fish_all_cells(&mut self) -> Vec<Deduction> {
+        let mut result = vec![];
+        self.find_fish(&mut result);
+        result
+    }
+
+    pub(crate) fn check_unique_number_left_all_cells(&mut self) -> Vec<Deduction> {
+        let mut result = vec![];
+        for which_rule in [WhichRule::Up, WhichRule::Left] {
+            for index in 0..self.number {
+                result.extend(self.check_line_view(which_rule, index, (0, 0), |line| {
+                    check_unique_number_left(line)
+                }));
+            }
+        }
+        result
+    }
+
+    pub(crate) fn check_hidden_subsets_all_cells(&mut self) -> Vec<Deduction> {
+        let max_size = self.max_hidden_subset_size;
+        let mut result = vec![];
+        for which_rule in [WhichRule::Up, WhichRule::Left] {
+            for index in 0..self.number {
+                result.extend(self.check_line_view(which_rule, index, (0, 0), |line| {
+                    check_hidden_subsets(line, max_size)
+                }));
+            }
+        }
+        result
+    }
+
+    pub(crate) fn check_opposite_rules_all_cells(&mut self) -> Vec<Deduction> {
+        let mut result = vec![];
+        for (near, far) in [
+            (WhichRule::Up, WhichRule::Down),
+            (WhichRule::Left, WhichRule::Right),
+        ] {
+            for index in 0..self.number {
+                let rules = (self.get_rule(near, index), self.get_rule(far, index));
+                if rules.0 > 0 && rules.1 > 0 {
+                    result.extend(self.check_line_view(near, index, rules, |line| {
+                        check_cell_line_by_opposite_rules(line, rules.0, rules.1)
+                    }));
+                }
+            }
+        }
+        result
+    }
+
+    pub(crate) fn check_visibility_bounds_all_cells(&mut self) -> Vec<Deduction> {
+        let mut result = vec![];
+        for which_rule in WhichRule::ALL {
+            for index in 0..self.number {
+                let rule = self.get_rule(which_rule, index);
+                if rule > 0 {
+                    result.extend(self.check_line_view(which_rule, index, (rule, 0), |line| {
+                        check_cell_line_by_visibility_bounds(line, rule)
+                    }));
+                }
+            }
+        }
+        result
+    }
+
+    pub(crate) fn check_line_permutations_all_cells(&mut self) -> Vec<Deduction> {
+        let mut result = vec![];
+        for (near, far) in [
+            (WhichRule::Up, WhichRule::Down),
+            (WhichRule::Left, WhichRule::Right),
+        ] {
+            for index in 0..self.number {
+                let rules = (self.get_rule(near, index), self.get_rule(far, index));
+                result.extend(self.check_line_view(near, index, rules, |line| {
+                    check_line_by_permutations(line, rules.0, rules.1)
+                }));
+            }
+        }
+        result
+    }
+
+    pub(crate) fn check_line(&mut self, which_rule: WhichRule) -> Vec<Deduction> {
+        let mut result = vec![];
+        for index in 0..self.number {
+            let rule = self.get_rule(which_rule, index);
+            if rule > 0 {
+                result.extend(self.check_line_view(which_rule, index, (rule, 0), |line| {
+                    check_cell_line_by_rule(rule, line)
+                }));
+            }
+        }
+        result
+    }
+}
+
+// The deductions based on `area` that took a cell from `old` to `new`.
+fn push_deductions(
+    area: Area,
+    (row, col): (usize, usize),
+    old: Cell,
+    new: Cell,
+    deductions: &mut Vec<Deduction>,
+) {
+    let deduction = |number, placed| Deduction {
+        technique: "",
+        area,
+        row,
+        col,
+        number,
+        placed,
+    };
+    for number in old.iter().filter(|&number| !new.contains(number)) {
+        deductions.push(deduction(number, false));
+    }
+    if let (Some(number), None) = (new.value(), old.value()) {
+        deductions.push(deduction(number, true));
     }
 }
 
-fn check_cell_line_by_rule(rule: usize, cells: &mut Vec<Cell>) {
+fn check_cell_line_by_rule<L: Line + ?Sized>(rule: usize, cells: &mut L) {
     let max_number = cells[0].n;
     if max_number <= 2 {
         return;
@@ -367,7 +1233,7 @@ fn check_cell_line_by_rule(rule: usize, cells: &mut Vec<Cell>) {
     } else if rule == 2 {
         check_cell_line_by_rule_2_all_checks(cells);
     } else if rule > 2 && rule < max_number {
-        check_cell_line_by_rule_mid_all_checks(cells, rule);
+        check_cell_line_by_rule_mid_simple_check(cells, rule);
     } else if rule == max_number {
         for i in 0..max_number {
             cells[i].replace_cell_with_number(i + 1);
@@ -375,33 +1241,30 @@ fn check_cell_line_by_rule(rule: usize, cells: &mut Vec<Cell>) {
     }
 }
 
-fn check_cell_line_by_rule_2_all_checks(cells: &mut Vec<Cell>) {
+fn check_cell_line_by_rule_2_all_checks<L: Line + ?Sized>(cells: &mut L) {
     check_cell_line_by_rule_2_simple_check(cells);
     check_cell_line_by_rule_2_advanced_check(cells);
 }
 
-fn check_cell_line_by_rule_2_simple_check(cells: &mut Vec<Cell>) {
+fn check_cell_line_by_rule_2_simple_check<L: Line + ?Sized>(cells: &mut L) {
     let max_number = cells[0].n;
     cells[0].remove_number(max_number);
     cells[1].remove_number(max_number - 1);
 }
 
-fn check_cell_line_by_rule_2_advanced_check(cells: &mut Vec<Cell>) {
+fn check_cell_line_by_rule_2_advanced_check<L: Line + ?Sized>(cells: &mut L) {
     let max_number = cells[0].n;
-    let first_cell_max_number = cells[0].numbers.last().unwrap().clone();
-    let mut position_of_first_max = 0;
-    for cell in &mut *cells {
-        if cell.numbers.contains(&max_number) {
-            break;
-        }
-        position_of_first_max += 1;
-    }
+    let first_cell_max_number = cells[0].max().unwrap_or(max_number);
+    let position_of_first_max = (0..cells.len())
+        .find(|&i| cells[i].contains(max_number))
+        .unwrap_or(cells.len());
 
     // Remove all the max that the first cell can't reach e.g.
     // n = 5: 123, 1234, 12345, 12345, 12345 --> 123, 1234, 12345, 12345, 1234_
     if max_number - first_cell_max_number > 1 {
         let how_many_last_cells = (max_number - first_cell_max_number) - 1;
-        for i in cells.len() - how_many_last_cells..cells.len() {
+        let first_unreachable = cells.len() - how_many_last_cells;
+        for i in first_unreachable..cells.len() {
             cells[i].remove_number(max_number);
         }
     }
@@ -409,7 +1272,8 @@ fn check_cell_line_by_rule_2_advanced_check(cells: &mut Vec<Cell>) {
     //between first cell and position of last remove the numbers between first_cell_max_number and max
     // n = 5: 123, 1234, 12345, 12345, 12345 --> 123, 12__, 12345, 12345, 1234_
     let numbers_between_maxes: Vec<usize> = (first_cell_max_number..max_number).collect();
-    for i in 1..position_of_first_max + 1 {
+    let last_before_max = position_of_first_max.min(cells.len() - 1);
+    for i in 1..last_before_max + 1 {
         cells[i].remove_vec(&numbers_between_maxes);
     }
 
@@ -421,12 +1285,7 @@ fn check_cell_line_by_rule_2_advanced_check(cells: &mut Vec<Cell>) {
     }
 }
 
-fn check_cell_line_by_rule_mid_all_checks(cells: &mut Vec<Cell>, rule: usize) {
-    check_cell_line_by_rule_mid_simple_check(cells, rule);
-    check_cell_line_by_rule_mid_atopo_max_num(cells, rule);
-}
-
-fn check_cell_line_by_rule_mid_simple_check(cells: &mut Vec<Cell>, rule: usize) {
+fn check_cell_line_by_rule_mid_simple_check<L: Line + ?Sized>(cells: &mut L, rule: usize) {
     let max_number = cells[0].n;
     if max_number <= 2 {
         return;
@@ -442,119 +1301,379 @@ fn check_cell_line_by_rule_mid_simple_check(cells: &mut Vec<Cell>, rule: usize)
     }
 }
 
-fn check_cell_line_by_rule_mid_atopo_max_num(cells: &mut Vec<Cell>, rule: usize) {
-    let max_number = cells[0].n;
-    if max_number <= 2 {
+// Removes every candidate that can't be placed without pushing `rule` out of the fewest
+// and most skyscrapers the line could still show.
+fn check_cell_line_by_visibility_bounds<L: Line + ?Sized>(cells: &mut L, rule: usize) {
+    let line = cells.to_vec();
+    if line.iter().all(|cell| cell.count() == 1) {
         return;
     }
-
-    for i in (0..max_number).rev() {
-        if cells[i].contains_numbers(&vec![max_number]) && cells[i].numbers.len() > 1 {
-            let mut temp_cells = cells.clone();
-            temp_cells[i].replace_cell_with_number(max_number);
-            for cell in &mut temp_cells {
-                cell.remove_number(max_number);
+    let mut memo = MaskMap::default();
+    if !get_visibility_bounds(&line, &mut memo)
+        .is_some_and(|(min, max)| (min..=max).contains(&rule))
+    {
+        return; // the line is broken, check_integrity reports it
+    }
+    for (i, bounds) in get_placement_visibility_bounds(&line, &mut memo)
+        .into_iter()
+        .enumerate()
+    {
+        for number in line[i].iter() {
+            if !bounds[number - 1].is_some_and(|(min, max)| (min..=max).contains(&rule)) {
+                cells[i].remove_number(number);
             }
+        }
+    }
+}
 
-            let max_skyscrapers = get_worst_case_min_number_of_skyscrapers(&temp_cells);
-            if max_skyscrapers > rule {
-                cells[i].remove_number(max_number);
+// For every cell i and number x (index x - 1), the fewest and most skyscrapers that can be
+// seen from the start of the line with x in cell i, or None if x can't go there at all.
+// The visible counts before cell i come from a pass over the line that keeps, for every set
+// of numbers placed so far, the fewest and most seen; the counts after it come from the
+// same search as get_visibility_bounds.
+fn get_placement_visibility_bounds(
+    cells: &[Cell],
+    memo: &mut MaskMap<u32, u64>,
+) -> Vec<Vec<Option<(usize, usize)>>> {
+    let mut result: Vec<Vec<Option<(usize, usize)>>> = vec![vec![None; cells.len()]; cells.len()];
+    let mut layer: MaskMap<u32, (usize, usize)> = MaskMap::default();
+    layer.insert(0, (0, 0));
+    for i in 0..cells.len() {
+        let mut next_layer: MaskMap<u32, (usize, usize)> = MaskMap::default();
+        for (&used, &(before_min, before_max)) in &layer {
+            let tallest = 32 - used.leading_zeros() as usize;
+            for number in cells[i].iter() {
+                let bit = 1 << (number - 1);
+                if used & bit != 0 {
+                    continue;
+                }
+                let after =
+                    get_possible_numbers_of_skyscrapers_from(&cells[i + 1..], used | bit, memo);
+                if after == 0 {
+                    continue;
+                }
+                let seen = usize::from(number > tallest);
+                let (min, max) = (before_min + seen, before_max + seen);
+                let bounds = (
+                    min + after.trailing_zeros() as usize,
+                    max + 63 - after.leading_zeros() as usize,
+                );
+                let placed = &mut result[i][number - 1];
+                *placed = Some(match *placed {
+                    Some((low, high)) => (low.min(bounds.0), high.max(bounds.1)),
+                    None => bounds,
+                });
+                let next = next_layer.entry(used | bit).or_insert((min, max));
+                *next = (next.0.min(min), next.1.max(max));
             }
         }
+        layer = next_layer;
     }
+    result
 }
 
-fn get_worst_case_min_number_of_skyscrapers(cells: &Vec<Cell>) -> usize {
-    let max_number = cells[0].n;
-    let mut temp_cells = cells.clone();
-    let mut solved_nums: Vec<usize> = vec![];
+// The fewest and most skyscrapers that can be seen from the start of the line, over every
+// arrangement the candidates still allow. None if there is no arrangement.
+fn get_visibility_bounds(cells: &[Cell], memo: &mut MaskMap<u32, u64>) -> Option<(usize, usize)> {
+    match get_possible_numbers_of_skyscrapers_from(cells, 0, memo) {
+        0 => None,
+        possible => Some((
+            possible.trailing_zeros() as usize,
+            63 - possible.leading_zeros() as usize,
+        )),
+    }
+}
 
-    for i in 0..max_number {
-        if temp_cells[i].numbers.len() == 1 {
-            solved_nums.push(temp_cells[i].numbers[0]);
+// Bit k is set if some arrangement the candidates allow shows k skyscrapers from the start
+// of the line. `used` has bit (x - 1) set for every number x placed before `cells`; the
+// tallest skyscraper so far is the highest of them, so the answer only depends on `used`.
+fn get_possible_numbers_of_skyscrapers_from(
+    cells: &[Cell],
+    used: u32,
+    memo: &mut MaskMap<u32, u64>,
+) -> u64 {
+    if cells.is_empty() {
+        return 1;
+    }
+    if let Some(&result) = memo.get(&used) {
+        return result;
+    }
+    let tallest = 32 - used.leading_zeros() as usize;
+    let mut result = 0;
+    for number in cells[0].iter() {
+        let bit = 1 << (number - 1);
+        if used & bit != 0 {
+            continue;
         }
+        let rest = get_possible_numbers_of_skyscrapers_from(&cells[1..], used | bit, memo);
+        result |= if number > tallest { rest << 1 } else { rest };
     }
+    memo.insert(used, result);
+    result
+}
 
-    for i in 0..max_number {
-        if temp_cells[i].numbers.len() > 1 {
-            let x: Vec<usize> = temp_cells[i].numbers.clone().into_iter().rev().collect();
-            for biggest_num in x {
-                if !solved_nums.contains(&biggest_num) {
-                    temp_cells[i].replace_cell_with_number(biggest_num);
-                    solved_nums.push(biggest_num);
-                    for cell in &mut temp_cells {
-                        cell.remove_number(biggest_num);
-                    }
-                    break;
-                }
+// Uses the rules at both ends of a line together to place the two tallest skyscrapers.
+// The tallest (max) is seen from both ends, so it needs near_rule - 1 cells before it and
+// far_rule - 1 after it. The second tallest (max - 1) is seen from the end on its side of
+// max, and exactly rule - 2 skyscrapers are seen before it from that end. With rules 2 and
+// 2 for example it can only be the first or the last cell, which neither rule says alone.
+fn check_cell_line_by_opposite_rules<L: Line + ?Sized>(
+    cells: &mut L,
+    near_rule: usize,
+    far_rule: usize,
+) {
+    let max_number = cells[0].n;
+    if max_number <= 2 || near_rule == 0 || far_rule == 0 {
+        return;
+    }
+    // The number of cells before the second tallest when it is seen `rule` times from
+    // that end, counting the tallest.
+    let fits_second = |cells_before: usize, rule: usize| {
+        if rule == 2 {
+            cells_before == 0
+        } else {
+            rule > 2 && cells_before >= rule - 2
+        }
+    };
+
+    let mut max_positions = vec![false; max_number];
+    let mut second_positions = vec![false; max_number];
+    for max_pos in 0..max_number {
+        if !cells[max_pos].contains(max_number)
+            || max_pos + 1 < near_rule
+            || max_number - max_pos < far_rule
+        {
+            continue;
+        }
+        for second_pos in 0..max_number {
+            if second_pos == max_pos || !cells[second_pos].contains(max_number - 1) {
+                continue;
+            }
+            let fits = if second_pos < max_pos {
+                fits_second(second_pos, near_rule)
+            } else {
+                fits_second(max_number - 1 - second_pos, far_rule)
+            };
+            if fits {
+                max_positions[max_pos] = true;
+                second_positions[second_pos] = true;
             }
         }
     }
+    if !max_positions.contains(&true) {
+        return; // the line is broken, check_integrity reports it
+    }
 
-    let mut result: usize = 0;
-    let mut last_skyscraper = 0;
-    for cell in temp_cells {
-        assert!(cell.numbers.len() == 1);
-        let cell_skyscraper = cell.numbers[0];
-        if cell_skyscraper > last_skyscraper {
-            last_skyscraper = cell_skyscraper;
-            result += 1;
+    for (number, positions) in [
+        (max_number, max_positions),
+        (max_number - 1, second_positions),
+    ] {
+        for (i, &possible) in positions.iter().enumerate() {
+            if !possible {
+                cells[i].remove_number(number);
+            }
+        }
+        if positions.iter().filter(|&&possible| possible).count() == 1 {
+            let position = positions.iter().position(|&possible| possible).unwrap();
+            cells[position].replace_cell_with_number(number);
         }
     }
+}
 
-    result
+// Keeps in every cell only the numbers that appear there in at least one arrangement of
+// the line that fits the candidates, the rule at the start and the rule at the end (0 for
+// no rule). Leaves the line alone if no arrangement fits; check_integrity reports that.
+fn check_line_by_permutations<L: Line + ?Sized>(cells: &mut L, near_rule: usize, far_rule: usize) {
+    let line = cells.to_vec();
+    let mut allowed = vec![0; line.len()];
+    let rules = (near_rule, far_rule);
+    if !find_line_permutations(
+        &line,
+        0,
+        (0, 0),
+        rules,
+        &mut allowed,
+        &mut MaskMap::default(),
+    ) {
+        return;
+    }
+    for (i, allowed) in allowed.into_iter().enumerate() {
+        cells[i].keep_mask(allowed);
+    }
 }
 
-fn check_exclusive_numbers(cells: &mut Vec<Cell>) {
-    let mut occurances: HashMap<usize, usize> = HashMap::new();
+// Places the numbers of the line from the start, `used` has bit (x - 1) set for every
+// number x placed so far. A number is seen from the start if it is higher than every
+// number before it and from the end if it is higher than every number not placed yet, so
+// `visible` (from the start, from the end) only depends on the path to `used`. Marks in
+// `allowed` every number that is part of an arrangement meeting `rules`.
+fn find_line_permutations(
+    cells: &[Cell],
+    used: u32,
+    visible: (usize, usize),
+    rules: (usize, usize),
+    allowed: &mut [u32],
+    memo: &mut MaskMap<(u32, usize, usize), bool>,
+) -> bool {
+    let index = used.count_ones() as usize;
+    if index == cells.len() {
+        let meets = |visible: usize, rule: usize| rule == 0 || visible == rule;
+        return meets(visible.0, rules.0) && meets(visible.1, rules.1);
+    }
+
+    // The tallest number left is seen from the end, and from the start if it is higher
+    // than everything placed. At most one more per cell left is seen from the end, and
+    // from the start only the numbers left that are higher than everything placed.
+    let all_numbers = u32::MAX >> (32 - cells.len());
+    let highest = |numbers: u32| 32 - numbers.leading_zeros() as usize;
+    let higher_left = (all_numbers & !used)
+        .checked_shr(highest(used) as u32)
+        .unwrap_or(0);
+    let near_range = (
+        visible.0 + usize::from(higher_left != 0),
+        visible.0 + higher_left.count_ones() as usize,
+    );
+    let far_range = (visible.1 + 1, visible.1 + cells.len() - index);
+    let fits = |(low, high): (usize, usize), rule: usize| rule == 0 || (low..=high).contains(&rule);
+    if !fits(near_range, rules.0) || !fits(far_range, rules.1) {
+        return false;
+    }
+    if let Some(&result) = memo.get(&(used, visible.0, visible.1)) {
+        return result;
+    }
+
+    let mut result = false;
+    for number in cells[index].iter() {
+        let bit = 1 << (number - 1);
+        if used & bit != 0 {
+            continue;
+        }
+        let next_visible = (
+            visible.0 + usize::from(number > highest(used)),
+            visible.1 + usize::from(number > highest(all_numbers & !(used | bit))),
+        );
+        if find_line_permutations(cells, used | bit, next_visible, rules, allowed, memo) {
+            allowed[index] |= bit;
+            result = true;
+        }
+    }
+    memo.insert((used, visible.0, visible.1), result);
+    result
+}
 
-    for cell in &mut *cells {
-        for num in &cell.numbers {
-            let counter = occurances.entry(*num).or_insert(0);
-            *counter += 1;
+// Looks for k cells (2 <= k <= max_size) whose numbers together are exactly k numbers.
+// Those numbers have to go in these cells, so they are removed from the rest of the line.
+fn check_naked_subsets<L: Line + ?Sized>(cells: &mut L, max_size: usize) -> Vec<NakedSubset> {
+    let mut result = vec![];
+    for size in 2..=max_size.min(cells.len().saturating_sub(1)) {
+        let open: Vec<usize> = (0..cells.len())
+            .filter(|&i| (2..=size).contains(&cells[i].count()))
+            .collect();
+        let masks: Vec<u32> = (0..cells.len()).map(|i| cells[i].mask()).collect();
+        let mut subsets = vec![];
+        find_subsets(&masks, &open, size, &mut vec![], 0, &mut subsets);
+        for subset in subsets {
+            // An earlier subset may have taken numbers out of this one.
+            let mask = subset.iter().fold(0, |mask, &i| mask | cells[i].mask());
+            if mask.count_ones() as usize != size {
+                continue;
+            }
+            let numbers: Vec<usize> = (1..=cells[0].n)
+                .filter(|number| mask & 1 << (number - 1) != 0)
+                .collect();
+            let mut removed = vec![];
+            for i in (0..cells.len()).filter(|i| !subset.contains(i)) {
+                for &number in &numbers {
+                    if cells[i].contains(number) && cells[i].count() > 1 {
+                        cells[i].remove_number(number);
+                        removed.push((cells.position(i), number));
+                    }
+                }
+            }
+            if !removed.is_empty() {
+                result.push(NakedSubset {
+                    cells: subset.iter().map(|&i| cells.position(i)).collect(),
+                    numbers,
+                    removed,
+                });
+            }
         }
     }
+    result
+}
 
-    let mut frequencies: HashMap<usize, Vec<usize>> = HashMap::new();
+// Collects every group of `size` items out of `open` whose masks together have exactly
+// `size` bits set. The items are cells with their numbers for naked subsets, and numbers
+// with the cells they can go in for hidden subsets.
+fn find_subsets(
+    masks: &[u32],
+    open: &[usize],
+    size: usize,
+    chosen: &mut Vec<usize>,
+    union: u32,
+    subsets: &mut Vec<Vec<usize>>,
+) {
+    if chosen.len() == size {
+        if union.count_ones() as usize == size {
+            subsets.push(chosen.clone());
+        }
+        return;
+    }
+    for (j, &i) in open.iter().enumerate() {
+        let union = union | masks[i];
+        // Adding items never clears bits, so this group can't work anymore.
+        if union.count_ones() as usize > size {
+            continue;
+        }
+        chosen.push(i);
+        find_subsets(masks, &open[j + 1..], size, chosen, union, subsets);
+        chosen.pop();
+    }
+}
 
-    for (num, freq) in &occurances {
-        let numbers = frequencies.entry(*freq).or_insert(vec![]);
-        (*numbers).push(*num);
+// For every number (index number - 1), the cells of the line it can still go in, with bit
+// i set for cell i.
+fn get_number_places<L: Line + ?Sized>(cells: &L) -> Vec<u32> {
+    let mut places = vec![0; cells[0].n];
+    for i in 0..cells.len() {
+        for number in cells[i].iter() {
+            places[number - 1] |= 1 << i;
+        }
     }
+    places
+}
 
-    for (freq, numbers) in &mut frequencies {
-        if *freq == numbers.len() {
-            for cell in &mut *cells {
-                if cell.contains_numbers(numbers) {
-                    numbers.sort();
-                    cell.replace_cell_with_vec(numbers);
+// Looks for k numbers (2 <= k <= max_size) that can only go in the same k cells. Those
+// cells have to hold these numbers, so everything else is removed from them.
+fn check_hidden_subsets<L: Line + ?Sized>(cells: &mut L, max_size: usize) {
+    for size in 2..=max_size.min(cells.len().saturating_sub(1)) {
+        let places = get_number_places(cells);
+        let open: Vec<usize> = (0..places.len())
+            .filter(|&index| (1..=size).contains(&(places[index].count_ones() as usize)))
+            .collect();
+        let mut subsets = vec![];
+        find_subsets(&places, &open, size, &mut vec![], 0, &mut subsets);
+        for subset in subsets {
+            let numbers = subset.iter().fold(0, |mask, &index| mask | 1 << index);
+            let in_cells = subset.iter().fold(0, |mask, &index| mask | places[index]);
+            for i in 0..cells.len() {
+                if in_cells & 1 << i != 0 {
+                    cells[i].keep_mask(numbers);
                 }
             }
         }
     }
 }
 
-// This doesn't work well ! Check it more.
-fn check_unique_number_left(cells: &mut Vec<Cell>) {
-    let mut number_count: Vec<usize> = vec![0; cells[0].n];
-
-    for cell in cells.clone() {
-        for num in cell.numbers {
-            number_count[num - 1] += 1;
-        }
-    }
-    let mut index = 0;
-    for n_count in number_count {
-        for cell in &mut *cells {
-            if n_count == 1 {
-                if cell.numbers.len() > 1 {
-                    if cell.numbers.contains(&(&index + 1)) {
-                        cell.replace_cell_with_number(index + 1)
-                    }
-                }
-            }
+// Places every number that can only go in one cell of the line.
+fn check_unique_number_left<L: Line + ?Sized>(cells: &mut L) {
+    for (index, places) in get_number_places(cells).into_iter().enumerate() {
+        if places.count_ones() == 1 {
+            let i = places.trailing_zeros() as usize;
+            // keep_mask leaves the cell alone if another number is unique to it as well,
+            // which can only happen on a broken board.
+            cells[i].keep_mask(1 << index);
         }
-        index += 1;
     }
 }
 
@@ -753,10 +1872,68 @@ mod tests {
                 Cell::new_cell_fixed(n, vec![2, 3]),
             ]
         );
+
+        let n: usize = 4;
+        // 1 and 4 can both only go in the first cell, so the line is broken and the cell
+        // keeps the first of them instead of losing both.
+        let mut two_unique_in_one_cell = vec![
+            Cell::new_cell_fixed(n, vec![1, 2, 4]),
+            Cell::new_cell_fixed(n, vec![2, 3]),
+            Cell::new_cell_fixed(n, vec![2, 3]),
+            Cell::new_cell_fixed(n, vec![2, 3]),
+        ];
+        check_unique_number_left(&mut two_unique_in_one_cell);
+        assert_eq!(two_unique_in_one_cell[0], Cell::new_cell_fixed(n, vec![1]));
+    }
+
+    #[test]
+    fn test_check_hidden_subsets() {
+        let n: usize = 5;
+        // transform |12345, 12345, 123, 123, 123| --> |45, 45, 123, 123, 123|
+        let mut hidden_pair = vec![
+            Cell::new_cell(n),
+            Cell::new_cell(n),
+            Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            Cell::new_cell_fixed(n, vec![1, 2, 3]),
+        ];
+        check_hidden_subsets(&mut hidden_pair, 2);
+        assert_eq!(hidden_pair[0], Cell::new_cell_fixed(n, vec![4, 5]));
+        assert_eq!(hidden_pair[1], Cell::new_cell_fixed(n, vec![4, 5]));
+        assert_eq!(hidden_pair[2], Cell::new_cell_fixed(n, vec![1, 2, 3]));
+
+        let n: usize = 6;
+        // 4, 5 and 6 only go in the first three cells, but no two of them share two cells
+        // transform |12346, 1245, 356, 123, 123, 123| --> |46, 45, 56, 123, 123, 123|
+        let hidden_triple = vec![
+            Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 6]),
+            Cell::new_cell_fixed(n, vec![1, 2, 4, 5]),
+            Cell::new_cell_fixed(n, vec![3, 5, 6]),
+            Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            Cell::new_cell_fixed(n, vec![1, 2, 3]),
+        ];
+        let mut only_pairs = hidden_triple.clone();
+        check_hidden_subsets(&mut only_pairs, 2);
+        assert_eq!(only_pairs, hidden_triple);
+
+        let mut with_triples = hidden_triple.clone();
+        check_hidden_subsets(&mut with_triples, 3);
+        assert_eq!(
+            with_triples,
+            vec![
+                Cell::new_cell_fixed(n, vec![4, 6]),
+                Cell::new_cell_fixed(n, vec![4, 5]),
+                Cell::new_cell_fixed(n, vec![5, 6]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            ]
+        );
     }
 
     #[test]
-    fn test_check_exclusive_numbers() {
+    fn test_check_hidden_subsets_same_cells() {
         let n: usize = 5;
         // transform |123, 12345, 123, 12345, 123| --> |123, 45, 123, 45, 123|
         let mut four_five_are_exclusive = vec![
@@ -766,7 +1943,7 @@ mod tests {
             Cell::new_cell(n),
             Cell::new_cell_fixed(n, vec![1, 2, 3]),
         ];
-        check_exclusive_numbers(&mut four_five_are_exclusive);
+        check_hidden_subsets(&mut four_five_are_exclusive, 4);
         assert_eq!(
             four_five_are_exclusive,
             vec![
@@ -780,10 +1957,104 @@ mod tests {
     }
 
     #[test]
-    fn test_place_max_num_leads_to_atopo() {
+    fn test_check_hidden_subsets_in_different_cells() {
+        let n: usize = 4;
+        // 3 only in cells 1, 2 and 4 only in cells 1, 3: cell 1 can still be 1 or 2.
+        let mut not_exclusive = vec![
+            Cell::new_cell(n),
+            Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            Cell::new_cell_fixed(n, vec![1, 2, 4]),
+            Cell::new_cell_fixed(n, vec![1, 2]),
+        ];
+        check_hidden_subsets(&mut not_exclusive, 3);
+        assert_eq!(not_exclusive[0], Cell::new_cell(n));
+    }
+
+    #[test]
+    fn test_check_naked_subsets() {
+        let n: usize = 6;
+        // transform |12, 12, 1234, 123456, 356, 56| --> |12, 12, 34, 3456, 356, 56|
+        let mut pairs = vec![
+            Cell::new_cell_fixed(n, vec![1, 2]),
+            Cell::new_cell_fixed(n, vec![1, 2]),
+            Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
+            Cell::new_cell(n),
+            Cell::new_cell_fixed(n, vec![3, 5, 6]),
+            Cell::new_cell_fixed(n, vec![5, 6]),
+        ];
+        let subsets = check_naked_subsets(&mut pairs, 2);
+        assert_eq!(
+            subsets[0],
+            NakedSubset {
+                cells: vec![(0, 0), (0, 1)],
+                numbers: vec![1, 2],
+                removed: vec![((0, 2), 1), ((0, 2), 2), ((0, 3), 1), ((0, 3), 2)],
+            }
+        );
+        assert_eq!(
+            pairs,
+            vec![
+                Cell::new_cell_fixed(n, vec![1, 2]),
+                Cell::new_cell_fixed(n, vec![1, 2]),
+                Cell::new_cell_fixed(n, vec![3, 4]),
+                Cell::new_cell_fixed(n, vec![3, 4, 5, 6]),
+                Cell::new_cell_fixed(n, vec![3, 5, 6]),
+                Cell::new_cell_fixed(n, vec![5, 6]),
+            ]
+        );
+
+        let n: usize = 5;
+        // |12, 23, 13, 12345, 12345| only has the triple 123
+        let triple = vec![
+            Cell::new_cell_fixed(n, vec![1, 2]),
+            Cell::new_cell_fixed(n, vec![2, 3]),
+            Cell::new_cell_fixed(n, vec![1, 3]),
+            Cell::new_cell(n),
+            Cell::new_cell(n),
+        ];
+        let mut only_pairs = triple.clone();
+        assert!(check_naked_subsets(&mut only_pairs, 2).is_empty());
+        assert_eq!(only_pairs, triple);
+
+        let mut with_triples = triple.clone();
+        let subsets = check_naked_subsets(&mut with_triples, 3);
+        assert_eq!(subsets.len(), 1);
+        assert_eq!(subsets[0].cells, vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(subsets[0].numbers, vec![1, 2, 3]);
+        assert_eq!(with_triples[3], Cell::new_cell_fixed(n, vec![4, 5]));
+        assert_eq!(with_triples[4], Cell::new_cell_fixed(n, vec![4, 5]));
+    }
+
+    #[test]
+    fn test_check_cell_line_by_visibility_bounds() {
+        let n: usize = 5;
+        let rule: usize = 4;
+        // 4 first shows at most 2 and 1 last leaves 4 first, so
+        // transform 4 |14, 12345, 12345, 12345, 12345| -> |(1), _23__, _234_, _2345, _2345|
+        let mut test_cells = vec![
+            Cell::new_cell_fixed(n, vec![1, 4]),
+            Cell::new_cell(n),
+            Cell::new_cell(n),
+            Cell::new_cell(n),
+            Cell::new_cell(n),
+        ];
+        check_cell_line_by_visibility_bounds(&mut test_cells, rule);
+        assert_eq!(
+            test_cells,
+            vec![
+                Cell::new_cell_fixed(n, vec![1]),
+                Cell::new_cell_fixed(n, vec![2, 3]),
+                Cell::new_cell_fixed(n, vec![2, 3, 4]),
+                Cell::new_cell_fixed(n, vec![2, 3, 4, 5]),
+                Cell::new_cell_fixed(n, vec![2, 3, 4, 5]),
+            ]
+        );
+
         let n: usize = 7;
         let rule: usize = 3;
-        // transform 3 |123, 2345, 12345, 12345, 123457, (6), 123457| -> |123, 2345, 12345, 12345, 123457, (6), 12345_|
+        // 7 in the last cell shows at least 4. With 7 in the fifth cell the first four
+        // cells have to show 2, which 3 in the second cell can't do.
+        // transform 3 |123, 2345, 12345, 12345, 123457, (6), 123457| -> |123, 2_45, 12345, 12345, (7), (6), 12345_|
         let mut test_cells = vec![
             Cell::new_cell_fixed(n, vec![1, 2, 3]),
             Cell::new_cell_fixed(n, vec![2, 3, 4, 5]),
@@ -793,17 +2064,15 @@ mod tests {
             Cell::new_cell_fixed(n, vec![6]),
             Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5, 7]),
         ];
-
-        check_cell_line_by_rule_mid_atopo_max_num(&mut test_cells, rule);
-
+        check_cell_line_by_visibility_bounds(&mut test_cells, rule);
         assert_eq!(
             test_cells,
             vec![
                 Cell::new_cell_fixed(n, vec![1, 2, 3]),
-                Cell::new_cell_fixed(n, vec![2, 3, 4, 5]),
+                Cell::new_cell_fixed(n, vec![2, 4, 5]),
                 Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5]),
                 Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5]),
-                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5, 7]),
+                Cell::new_cell_fixed(n, vec![7]),
                 Cell::new_cell_fixed(n, vec![6]),
                 Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5]),
             ]
@@ -811,7 +2080,7 @@ mod tests {
     }
 
     #[test]
-    fn test_get_worst_case_min_number_of_skyscrapers() {
+    fn test_get_visibility_bounds() {
         let n: usize = 5;
         let asc_cells = vec![
             Cell::new_cell_fixed(n, vec![1]),
@@ -820,13 +2089,7 @@ mod tests {
             Cell::new_cell_fixed(n, vec![4]),
             Cell::new_cell_fixed(n, vec![5]),
         ];
-        let dsc_cells = vec![
-            Cell::new_cell_fixed(n, vec![5]),
-            Cell::new_cell_fixed(n, vec![4]),
-            Cell::new_cell_fixed(n, vec![3]),
-            Cell::new_cell_fixed(n, vec![2]),
-            Cell::new_cell_fixed(n, vec![1]),
-        ];
+        let open_cells = vec![Cell::new_cell(n); n];
         let random_cells = vec![
             Cell::new_cell_fixed(n, vec![1, 2, 3]),
             Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
@@ -834,8 +2097,435 @@ mod tests {
             Cell::new_cell(n),
             Cell::new_cell_fixed(n, vec![1, 2, 3]),
         ];
-        assert_eq!(get_worst_case_min_number_of_skyscrapers(&asc_cells), 5);
-        assert_eq!(get_worst_case_min_number_of_skyscrapers(&dsc_cells), 1);
-        assert_eq!(get_worst_case_min_number_of_skyscrapers(&random_cells), 3);
+        let broken_cells = vec![Cell::new_cell_fixed(n, vec![1, 2]); n];
+        let bounds = |cells: &[Cell]| get_visibility_bounds(cells, &mut MaskMap::default());
+        assert_eq!(bounds(&asc_cells), Some((5, 5)));
+        assert_eq!(bounds(&open_cells), Some((1, 5)));
+        assert_eq!(bounds(&random_cells), Some((3, 3)));
+        assert_eq!(bounds(&broken_cells), None);
+
+        // Filling with the biggest numbers first gives 2, 3, 4, 1 -> 3, but 2, 1, 4, 3
+        // only shows 2.
+        let n: usize = 4;
+        let cells = vec![
+            Cell::new_cell_fixed(n, vec![2]),
+            Cell::new_cell_fixed(n, vec![1, 3]),
+            Cell::new_cell_fixed(n, vec![4]),
+            Cell::new_cell_fixed(n, vec![1, 3]),
+        ];
+        assert_eq!(bounds(&cells), Some((2, 3)));
+    }
+
+    #[test]
+    fn test_remove_fish() {
+        // 1 only goes in cols 2 and 4 of rows 1 and 3
+        let mut board = Board::new_board(4);
+        for (row, col) in [(0, 0), (0, 2), (2, 0), (2, 2)] {
+            board.cells[row][col].remove_number(1);
+        }
+        assert_eq!(
+            board.remove_fish(),
+            vec![Fish {
+                number: 1,
+                in_rows: true,
+                lines: vec![0, 2],
+                cover: vec![1, 3],
+                removed: vec![(1, 1), (1, 3), (3, 1), (3, 3)],
+            }]
+        );
+        let removed = board.get_deduction(1, 1, 1).unwrap();
+        assert_eq!(removed.technique, "fish");
+        assert_eq!(
+            removed.to_string(),
+            "X-Wing of 1 in rows 1, 3: 1 cannot be in cell 22"
+        );
+        assert!(board.remove_fish().is_empty());
+
+        // 1 only goes in rows 1 to 3 of cols 1, 3 and 5, two of them in each col. Rows 4 to
+        // 6 then only have cols 2, 4 and 6 left for it, which is found first.
+        let mut board = Board::new_board(6);
+        for (col, rows) in [(0, [1, 2]), (2, [0, 1]), (4, [0, 2])] {
+            for row in (0..6).filter(|row| !rows.contains(row)) {
+                board.cells[row][col].remove_number(1);
+            }
+        }
+        let fish = board.remove_fish();
+        assert_eq!(fish.len(), 1);
+        assert_eq!(fish[0].name(), "Swordfish");
+        assert!(fish[0].in_rows);
+        assert_eq!(fish[0].lines, vec![3, 4, 5]);
+        assert_eq!(fish[0].cover, vec![1, 3, 5]);
+        assert_eq!(fish[0].removed.len(), 9);
+        for row in 0..3 {
+            for col in [1, 3, 5] {
+                assert!(!board.cells[row][col].contains(1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_deduction_log() {
+        let mut board = Board::new_board(4);
+        board.update_rule_x("1000", WhichRule::Up);
+        for index in 0..board.techniques().entries().len() {
+            let name = board.techniques().entries()[index].technique.name();
+            let enabled = name == "rules up" || name == "solved cells";
+            board.techniques_mut().set_enabled(index, enabled);
+        }
+        assert!(board.check_all().is_ok());
+
+        let placed = board.get_deduction(0, 0, 4).unwrap();
+        assert_eq!(placed.technique, "rules up");
+        assert_eq!(
+            placed.to_string(),
+            "col 1, clue 1 from top: 4 must be in row 1"
+        );
+        let removed = board.get_deduction(0, 0, 1).unwrap();
+        assert_eq!(
+            removed.to_string(),
+            "col 1, clue 1 from top: 1 cannot be in row 1"
+        );
+        let removed = board.get_deduction(0, 1, 4).unwrap();
+        assert_eq!(removed.technique, "solved cells");
+        assert_eq!(removed.to_string(), "cell 11 is 4: 4 cannot be in cell 12");
+        // 4 is still possible in cell 22
+        assert_eq!(board.get_deduction(1, 1, 4), None);
+
+        board.restore_cell("11");
+        assert_eq!(board.get_deduction(0, 0, 4), None);
+        assert_eq!(board.get_deduction(0, 0, 1), None);
+
+        // A naked pair in row 1
+        let mut board = Board::new_board(4);
+        for col in 0..2 {
+            board.cells[0][col].replace_cell_with_vec(&[1, 2]);
+        }
+        assert_eq!(board.remove_naked_subsets().len(), 1);
+        assert_eq!(board.deductions().len(), 4);
+        let removed = board.get_deduction(0, 2, 1).unwrap();
+        assert_eq!(removed.technique, "naked subsets");
+        assert_eq!(
+            removed.to_string(),
+            "cells 11, 12 only hold 1, 2: 1 cannot be in cell 13"
+        );
+    }
+
+    #[test]
+    fn test_step() {
+        let mut board = Board::new_board(4);
+        board.update_rule_x("1000", WhichRule::Up);
+        board.update_rule_x("0004", WhichRule::Left);
+
+        // Both rules are as easy, so the first one in the list goes first, one number at a
+        // time.
+        let step = board.step();
+        assert_eq!(step.len(), 1);
+        assert_eq!(step[0].technique, "rules up");
+        assert_eq!(
+            step[0].to_string(),
+            "col 1, clue 1 from top: 1 cannot be in row 1"
+        );
+        assert_eq!(board.cells[0][0], Cell::new_cell_fixed(4, vec![2, 3, 4]));
+        assert_eq!(board.step().len(), 1);
+        let step = board.step();
+        assert_eq!(step.len(), 2);
+        assert_eq!(
+            step[1].to_string(),
+            "col 1, clue 1 from top: 4 must be in row 1"
+        );
+        assert_eq!(board.cells[0][0].value(), Some(4));
+        assert_eq!(board.cells[3][0], Cell::new_cell(4));
+
+        let step = board.step();
+        assert_eq!(step.len(), 1);
+        assert_eq!(step[0].technique, "rules left");
+        assert_eq!(board.cells[3][0], Cell::new_cell_fixed(4, vec![1, 3, 4]));
+        assert_eq!(board.deductions().len(), 5);
+
+        // step_area makes the rest of the line at once.
+        let step = board.step_area();
+        assert!(step
+            .iter()
+            .all(|deduction| deduction.technique == "rules left"));
+        assert_eq!(step.len(), 4 * 3 + 4 - 1);
+        assert_eq!(board.cells[3][0].value(), Some(1));
+    }
+
+    #[test]
+    fn test_hint() {
+        let mut board = Board::new_board(4);
+        board.update_rule_x("1000", WhichRule::Up);
+        let cells = board.cells.clone();
+
+        let (stage, step) = board.hint().unwrap();
+        assert_eq!(stage, 1);
+        assert_eq!(step[0].area.location(), "col 1");
+        assert_eq!(board.hint().unwrap().0, 2);
+        assert_eq!(board.hint().unwrap().0, 3);
+        assert_eq!(board.hint().unwrap().0, 3);
+        assert_eq!(board.cells, cells);
+        assert!(board.deductions().is_empty());
+
+        // The hint is the step that would be made, and starts over after it.
+        assert_eq!(board.clone().step(), step);
+        board.check_ok_cell("114");
+        let (stage, step) = board.hint().unwrap();
+        assert_eq!(stage, 1);
+        assert_eq!(step[0].to_string(), "cell 11 is 4: 4 cannot be in cell 21");
+
+        board.update_rule_x("4", WhichRule::Left);
+        assert_eq!(board.hint().unwrap().0, 1);
+    }
+
+    #[test]
+    fn test_givens() {
+        let mut board = Board::new_board(4);
+        board.set_given(1, 2, 4);
+        board.restore_cell("23");
+        board.remove_number_from_cell("234");
+        board.check_ok_cell("231");
+        assert_eq!(board.cells[1][2].value(), Some(4));
+        assert_eq!(board.get_given(1, 2), 4);
+
+        let solution = board.solve().unwrap();
+        assert_eq!(solution.get_given(1, 2), 4);
+        assert_eq!(solution.cells[1][2].value(), Some(4));
+
+        board.check_ok_cell("111");
+        let puzzle = board.to_puzzle();
+        assert_eq!(puzzle.cells[0][0], Cell::new_cell(4));
+        assert_eq!(puzzle.cells[1][2].value(), Some(4));
+
+        board.set_given(1, 2, 0);
+        assert_eq!(board.cells[1][2], Cell::new_cell(4));
+        board.check_ok_cell("231");
+        assert_eq!(board.cells[1][2].value(), Some(1));
+    }
+
+    fn new_board_with_rules(n: usize, up: &str, down: &str, left: &str, right: &str) -> Board {
+        let mut board = Board::new_board(n);
+        board.update_rule_x(up, WhichRule::Up);
+        board.update_rule_x(down, WhichRule::Down);
+        board.update_rule_x(left, WhichRule::Left);
+        board.update_rule_x(right, WhichRule::Right);
+        board
+    }
+
+    fn solved_values(board: &Board) -> Vec<Vec<usize>> {
+        board
+            .cells
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.value().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_solve() {
+        let board = new_board_with_rules(5, "30000", "03000", "02220", "30100");
+        let solution = board.solve().unwrap();
+        assert_eq!(
+            solved_values(&solution),
+            vec![
+                vec![2, 4, 5, 3, 1],
+                vec![1, 5, 3, 4, 2],
+                vec![4, 3, 1, 2, 5],
+                vec![3, 1, 2, 5, 4],
+                vec![5, 2, 4, 1, 3],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_solve_no_solution() {
+        // The same column can't be seen whole from both ends.
+        let board = new_board_with_rules(4, "4000", "4000", "0000", "0000");
+        assert!(board.solve().is_none());
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut board = new_board_with_rules(5, "30000", "03000", "02220", "30100");
+        assert_eq!(board.complete(Some(1)).rounds, 1);
+
+        let report = board.complete(None);
+        assert!(report.removed.iter().any(|(_, removed)| *removed > 0));
+
+        // Already at the fixpoint: one round that changes nothing.
+        let report = board.complete(None);
+        assert_eq!(report.rounds, 1);
+        assert!(report.removed.iter().all(|(_, removed)| *removed == 0));
+    }
+
+    #[test]
+    fn test_check_integrity() {
+        let mut board = new_board_with_rules(4, "4000", "0000", "0000", "0000");
+        assert!(board.check_integrity().is_empty());
+
+        // Column 1 is 2, 3, 4, 1 from the top, which shows 3 skyscrapers.
+        board.check_ok_cell("112");
+        board.check_ok_cell("213");
+        board.check_ok_cell("314");
+        board.check_ok_cell("411");
+        board.check_ok_cell("142");
+        for number in 1..5 {
+            board.cells[2][2].remove(number);
+        }
+        assert_eq!(
+            board.check_integrity(),
+            vec![
+                Contradiction::EmptyCell { row: 2, col: 2 },
+                Contradiction::Duplicate {
+                    number: 2,
+                    first: (0, 0),
+                    second: (0, 3),
+                },
+                Contradiction::UnreachableRule {
+                    which_rule: WhichRule::Up,
+                    index: 0,
+                    rule: 4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_integrity_visibility_bounds() {
+        // With 4 up, column 1 has to be 1, 2, 3, 4, so 2 on top can't work even though the
+        // column isn't solved yet.
+        let mut board = new_board_with_rules(4, "4000", "0000", "0000", "0000");
+        board.cells[0][0].replace_cell_with_number(2);
+        assert_eq!(
+            board.check_integrity(),
+            vec![Contradiction::UnreachableRule {
+                which_rule: WhichRule::Up,
+                index: 0,
+                rule: 4,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_count_solutions() {
+        let unique = new_board_with_rules(5, "30000", "03000", "02220", "30100");
+        assert_eq!(unique.count_solutions(10), 1);
+
+        // Every rule is given, and still rows 3 and 4 can be filled in two ways.
+        let two = new_board_with_rules(5, "22421", "23213", "41232", "12432");
+        assert_eq!(two.count_solutions(10), 2);
+        assert_eq!(two.count_solutions(1), 1);
+        let solutions = two.find_solutions(2);
+        assert!(!solutions[0].differences(&solutions[1]).is_empty());
+
+        let none = new_board_with_rules(4, "4000", "4000", "0000", "0000");
+        assert_eq!(none.count_solutions(10), 0);
+    }
+
+    #[test]
+    fn test_check_line_by_permutations() {
+        let n: usize = 4;
+        // 3 from the start and 2 from the end: 1243, 1342 or 2341.
+        let mut both_rules = vec![Cell::new_cell(n); n];
+        check_line_by_permutations(&mut both_rules, 3, 2);
+        assert_eq!(
+            both_rules,
+            vec![
+                Cell::new_cell_fixed(n, vec![1, 2]),
+                Cell::new_cell_fixed(n, vec![2, 3]),
+                Cell::new_cell_fixed(n, vec![4]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            ]
+        );
+
+        let n: usize = 5;
+        let mut far_rule_only = vec![Cell::new_cell(n); n];
+        check_line_by_permutations(&mut far_rule_only, 0, 2);
+        assert_eq!(far_rule_only[4], Cell::new_cell_fixed(n, vec![1, 2, 3, 4]));
+        assert_eq!(far_rule_only[3], Cell::new_cell_fixed(n, vec![1, 2, 3, 5]));
+
+        // No arrangement fits, so nothing changes.
+        let mut impossible = vec![Cell::new_cell(n); n];
+        check_line_by_permutations(&mut impossible, 5, 5);
+        assert_eq!(impossible, vec![Cell::new_cell(n); n]);
+    }
+
+    #[test]
+    fn test_check_line_by_permutations_is_stronger_than_rules() {
+        let n: usize = 7;
+        let lines = vec![
+            vec![Cell::new_cell(n); n],
+            vec![
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5, 6]),
+                Cell::new_cell(n),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5, 7]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5, 7]),
+                Cell::new_cell(n),
+            ],
+            vec![
+                Cell::new_cell_fixed(n, vec![1, 2, 3]),
+                Cell::new_cell_fixed(n, vec![2, 3, 4, 5]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5, 7]),
+                Cell::new_cell_fixed(n, vec![6]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4, 5, 7]),
+            ],
+        ];
+        for line in lines {
+            for rule in 1..n + 1 {
+                let mut allowed = vec![0; n];
+                let rules = (rule, 0);
+                if !find_line_permutations(
+                    &line,
+                    0,
+                    (0, 0),
+                    rules,
+                    &mut allowed,
+                    &mut MaskMap::default(),
+                ) {
+                    continue; // the rules may do anything on a broken line
+                }
+                let mut by_rule = line.clone();
+                check_cell_line_by_rule(rule, &mut by_rule);
+                let mut by_permutations = line.clone();
+                check_line_by_permutations(&mut by_permutations, rule, 0);
+                for (rule_cell, permutations_cell) in by_rule.iter().zip(&by_permutations) {
+                    assert!(rule_cell.contains_cell(permutations_cell));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_cell_line_by_opposite_rules() {
+        let n: usize = 5;
+        // 2 and 2: 4 has to be seen first from one of the ends.
+        let mut two_and_two = vec![Cell::new_cell(n); n];
+        check_cell_line_by_opposite_rules(&mut two_and_two, 2, 2);
+        assert_eq!(
+            two_and_two,
+            vec![
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 5]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 5]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 5]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
+            ]
+        );
+
+        // 2 and 4 add up to n + 1, which fixes 5 in the second cell.
+        let mut sum_is_n_plus_one = vec![Cell::new_cell(n); n];
+        check_cell_line_by_opposite_rules(&mut sum_is_n_plus_one, 2, 4);
+        assert_eq!(
+            sum_is_n_plus_one,
+            vec![
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
+                Cell::new_cell_fixed(n, vec![5]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3, 4]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3]),
+                Cell::new_cell_fixed(n, vec![1, 2, 3]),
+            ]
+        );
     }
 }
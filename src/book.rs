@@ -0,0 +1,130 @@
+use crate::generator::{generate_puzzle, generate_puzzle_with_grade, Symmetry};
+use crate::rng::Rng;
+use crate::{grade, Board, Grade, WhichRule};
+
+// The grades a mixed book goes through, one puzzle each.
+pub const MIXED_GRADES: [Grade; 4] = [Grade::Easy, Grade::Medium, Grade::Hard, Grade::Extreme];
+
+#[derive(Debug, Clone)]
+pub struct BookPuzzle {
+    pub id: String,
+    pub board: Board,
+    // None if the techniques alone can't solve it.
+    pub grade: Option<Grade>,
+}
+
+// A name for the puzzle that only depends on its size, rules and givens, so the same
+// puzzle keeps it in every book, e.g. "6-0F3A91C2".
+pub fn get_puzzle_id(board: &Board) -> String {
+    let number = board.get_number();
+    let mut numbers = vec![number];
+    for which_rule in WhichRule::ALL {
+        numbers.extend((0..number).map(|index| board.get_rule(which_rule, index)));
+    }
+    for row in 0..number {
+        numbers.extend((0..number).map(|col| board.get_given(row, col)));
+    }
+    // 64 bit FNV-1a
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for number in numbers {
+        hash ^= number as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{}-{:08X}", number, hash >> 32)
+}
+
+// A puzzle of size `number` for a book, of grade `target` if there is one. If no puzzle
+// of that grade turns up, it has whatever grade a puzzle with the fewest rules gets. None
+// like generate_puzzle if no grid of the size was the only solution of its rules.
+pub fn make_book_puzzle(number: usize, target: Option<Grade>, rng: &mut Rng) -> Option<BookPuzzle> {
+    let graded = target
+        .and_then(|target| generate_puzzle_with_grade(number, target, Symmetry::None, rng).ok());
+    let board = match graded {
+        Some((board, _)) => board,
+        None => generate_puzzle(number, Symmetry::None, rng)?,
+    };
+    Some(BookPuzzle {
+        id: get_puzzle_id(&board),
+        grade: grade(&board).grade,
+        board,
+    })
+}
+
+// Easiest first, then smallest first. Puzzles without a grade go last.
+pub fn sort_book_puzzles(puzzles: &mut [BookPuzzle]) {
+    puzzles.sort_by_key(|puzzle| {
+        (
+            puzzle.grade.is_none(),
+            puzzle.grade,
+            puzzle.board.get_number(),
+        )
+    });
+}
+
+// The text of a book: the puzzles in the order given with blank cells, then their
+// solutions.
+pub fn render_book(puzzles: &[BookPuzzle], seed: u64) -> String {
+    let mut book = format!("Skyscrapers, {} puzzles (seed {})\n\n", puzzles.len(), seed);
+    book.push_str(
+        "Put the numbers 1 to N once in every row and column of an NxN grid. A number is the\n\
+         height of a skyscraper, and a clue around the grid says how many skyscrapers are\n\
+         seen from there, as higher ones hide lower ones. Numbers in [ ] are given.\n\n",
+    );
+    for (i, puzzle) in puzzles.iter().enumerate() {
+        let grade = puzzle
+            .grade
+            .map_or("needs guessing".to_string(), |grade| grade.to_string());
+        let number = puzzle.board.get_number();
+        book.push_str(&format!(
+            "Puzzle {}: {}x{}, {} ({})\n",
+            i + 1,
+            number,
+            number,
+            grade,
+            puzzle.id
+        ));
+        book.push_str(&puzzle.board.render_board(false));
+        book.push('\n');
+    }
+    book.push_str("Solutions\n\n");
+    for (i, puzzle) in puzzles.iter().enumerate() {
+        book.push_str(&format!("Solution {} ({})\n", i + 1, puzzle.id));
+        if let Some(solution) = puzzle.board.solve() {
+            book.push_str(&solution.render_board(true));
+        }
+        book.push('\n');
+    }
+    book
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_book() {
+        let mut rng = Rng::new(1);
+        let mut puzzles: Vec<BookPuzzle> = [Grade::Extreme, Grade::Easy, Grade::Medium]
+            .into_iter()
+            .map(|target| make_book_puzzle(4, Some(target), &mut rng).unwrap())
+            .collect();
+        puzzles.push(make_book_puzzle(5, None, &mut rng).unwrap());
+        sort_book_puzzles(&mut puzzles);
+        let grades: Vec<Option<Grade>> = puzzles.iter().map(|puzzle| puzzle.grade).collect();
+        assert_eq!(
+            grades[..3],
+            [Some(Grade::Easy), Some(Grade::Medium), Some(Grade::Extreme)]
+        );
+        // Working on the cells doesn't change the puzzle.
+        let mut board = puzzles[0].board.clone();
+        board.complete(None);
+        assert_eq!(get_puzzle_id(&board), puzzles[0].id);
+        assert!(puzzles[0].id.starts_with("4-"));
+        assert_ne!(puzzles[0].id, puzzles[1].id);
+
+        let book = render_book(&puzzles, 1);
+        assert!(book.contains(&format!("Puzzle 1: 4x4, Easy ({})", puzzles[0].id)));
+        assert!(book.contains(&format!("Solution 4 ({})", puzzles[3].id)));
+        assert!(book.find("Solutions").unwrap() > book.find("Puzzle 4").unwrap());
+    }
+}
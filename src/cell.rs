@@ -1,83 +1,228 @@
-#[derive(Debug, Clone, PartialEq)]
+// The candidates of a cell are kept as a bitmask, bit (x - 1) is set if x is still
+// possible, so a cell is Copy and n can go up to Cell::MAX_NUMBER.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Cell {
     pub n: usize,
-    pub numbers: Vec<usize>,
+    mask: u32,
 }
 
 impl Cell {
+    pub const MAX_NUMBER: usize = 32;
+
     pub fn new_cell(number: usize) -> Cell {
+        assert!(
+            number <= Cell::MAX_NUMBER,
+            "a cell can't hold more than 32 numbers"
+        );
         Cell {
             n: number,
-            numbers: (1..number + 1).collect(),
+            mask: Cell::full_mask(number),
         }
     }
 
     #[cfg(test)]
     pub fn new_cell_fixed(number: usize, fixed_numbers: Vec<usize>) -> Cell {
-        Cell {
-            n: number,
-            numbers: fixed_numbers,
+        let mut cell = Cell::new_cell(number);
+        cell.mask = Cell::mask_of(&fixed_numbers);
+        cell
+    }
+
+    fn full_mask(number: usize) -> u32 {
+        if number == Cell::MAX_NUMBER {
+            u32::MAX
+        } else {
+            (1 << number) - 1
+        }
+    }
+
+    // Numbers a cell can't hold are left out.
+    fn mask_of(numbers: &[usize]) -> u32 {
+        numbers
+            .iter()
+            .filter(|&&number| (1..=Cell::MAX_NUMBER).contains(&number))
+            .fold(0, |mask, number| mask | 1 << (number - 1))
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn contains(&self, number: usize) -> bool {
+        number > 0 && number <= self.n && self.mask & 1 << (number - 1) != 0
+    }
+
+    // Unlike remove_number this can leave the cell empty. Returns true if the number was
+    // there.
+    pub fn remove(&mut self, number: usize) -> bool {
+        let had_number = self.contains(number);
+        if had_number {
+            self.mask &= !(1 << (number - 1));
+        }
+        had_number
+    }
+
+    pub fn count(&self) -> usize {
+        self.mask.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mask == 0
+    }
+
+    // The number of a solved cell.
+    pub fn value(&self) -> Option<usize> {
+        if self.count() == 1 {
+            Some(self.mask.trailing_zeros() as usize + 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn max(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(32 - self.mask.leading_zeros() as usize)
         }
     }
 
-    pub fn print_cell(&self) {
-        if self.numbers.len() == 1 && self.n >= 3 {
+    pub fn iter(&self) -> Numbers {
+        Numbers { mask: self.mask }
+    }
+
+    pub fn numbers(&self) -> Vec<usize> {
+        self.iter().collect()
+    }
+
+    pub fn render_cell(&self) -> String {
+        self.render_cell_in(('(', ')'))
+    }
+
+    // A given is shown like a solved cell, in square brackets.
+    pub fn render_given_cell(&self) -> String {
+        self.render_cell_in(('[', ']'))
+    }
+
+    fn render_cell_in(&self, brackets: (char, char)) -> String {
+        if let (Some(number), true) = (self.value(), self.n >= 3) {
             let adj_spaces = self.n / 2;
             let spaces_left = " ".repeat(adj_spaces - 1);
-            let spaces_right;
-            if self.n % 2 == 0 {
-                spaces_right = " ".repeat(adj_spaces - 2);
+            let spaces_right = if self.n.is_multiple_of(2) {
+                " ".repeat(adj_spaces - 2)
             } else {
-                spaces_right = spaces_left.clone();
-            }
-            print!("{}({}){}", spaces_left, self.numbers[0], spaces_right);
+                spaces_left.clone()
+            };
+            format!(
+                "{}{}{}{}{}",
+                spaces_left, brackets.0, number, brackets.1, spaces_right
+            )
         } else {
+            let mut numbers = String::new();
             for i in 1..self.n + 1 {
-                if self.numbers.contains(&i) {
-                    print!("{}", i);
+                if self.contains(i) {
+                    numbers.push_str(&i.to_string());
                 } else {
-                    print!(" ");
+                    numbers.push(' ');
                 }
             }
+            numbers
         }
     }
 
     pub fn replace_cell_with_number(&mut self, number: usize) {
         if number > 0 && number <= self.n {
-            self.numbers = vec![number];
+            self.mask = 1 << (number - 1);
         }
     }
 
-    pub fn replace_cell_with_vec(&mut self, numbers: &Vec<usize>) {
-        if numbers.len() > 0 && numbers.len() <= self.n {
-            self.numbers = numbers.clone();
+    // Like replace_cell_with_number, the cell is left alone if a number is out of range.
+    pub fn replace_cell_with_vec(&mut self, numbers: &[usize]) {
+        if !numbers.is_empty()
+            && numbers.len() <= self.n
+            && numbers.iter().all(|number| (1..=self.n).contains(number))
+        {
+            self.mask = Cell::mask_of(numbers);
         }
     }
 
-    pub fn contains_numbers(&self, numbers: &Vec<usize>) -> bool {
-        for num in numbers {
-            if !self.numbers.contains(num) {
-                return false;
-            }
+    // Keeps only the numbers of `mask`, unless that would leave the cell empty.
+    pub fn keep_mask(&mut self, mask: u32) {
+        if self.mask & mask != 0 {
+            self.mask &= mask;
         }
-        true
+    }
+
+    pub fn contains_numbers(&self, numbers: &[usize]) -> bool {
+        numbers.iter().all(|&number| self.contains(number))
+    }
+
+    // True if every number of `other` is also in this cell.
+    pub fn contains_cell(&self, other: &Cell) -> bool {
+        other.mask & !self.mask == 0
     }
 
     pub fn restore(&mut self) {
-        self.numbers = (1..self.n + 1).collect();
+        self.mask = Cell::full_mask(self.n);
     }
 
     pub fn remove_number(&mut self, number: usize) {
-        if self.numbers.contains(&number) && self.numbers.len() > 1 {
-            // self.numbers.remove(number);
-            self.numbers.retain(|&x| x != number);
+        if self.contains(number) && self.count() > 1 {
+            self.remove(number);
         }
     }
 
-    pub fn remove_vec(&mut self, numbers: &Vec<usize>) {
+    pub fn remove_vec(&mut self, numbers: &[usize]) {
         for number in numbers {
             self.remove_number(*number);
         }
+    }
+}
+
+// The numbers of a cell from lowest to highest.
+pub struct Numbers {
+    mask: u32,
+}
+
+impl Iterator for Numbers {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.mask == 0 {
+            return None;
+        }
+        let number = self.mask.trailing_zeros() as usize + 1;
+        self.mask &= self.mask - 1;
+        Some(number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_mask() {
+        let mut cell = Cell::new_cell(Cell::MAX_NUMBER);
+        assert_eq!(cell.count(), 32);
+        assert!(cell.remove(32));
+        assert!(!cell.remove(32));
+        assert!(!cell.contains(32));
+        assert_eq!(cell.max(), Some(31));
+
+        let mut cell = Cell::new_cell_fixed(5, vec![2, 4]);
+        assert_eq!(cell.numbers(), vec![2, 4]);
+        cell.remove_number(2);
+        cell.remove_number(4);
+        assert_eq!(cell.value(), Some(4));
+        cell.remove(4);
+        assert!(cell.is_empty());
 
+        let mut cell = Cell::new_cell(5);
+        cell.replace_cell_with_vec(&[0, 2]);
+        cell.replace_cell_with_vec(&[2, 33]);
+        cell.replace_cell_with_vec(&[6]);
+        assert_eq!(cell, Cell::new_cell(5));
+        cell.replace_cell_with_vec(&[2, 5]);
+        assert_eq!(cell.numbers(), vec![2, 5]);
     }
 }
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::generator::{generate_puzzle, Symmetry};
+use crate::rng::Rng;
+use crate::Board;
+
+// A day of the Gregorian calendar, written YYYY-MM-DD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    year: i64,
+    month: i64,
+    day: i64,
+}
+
+impl Date {
+    // The day it is now in UTC, so everyone gets the same one.
+    pub fn today() -> Date {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        Date::from_days(seconds as i64 / 86400)
+    }
+
+    // Days since 1970-01-01, from Howard Hinnant's days_from_civil.
+    pub fn to_days(&self) -> i64 {
+        let year = if self.month <= 2 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = (self.month + 9) % 12; // March is 0
+        let day_of_year = (153 * month + 2) / 5 + self.day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146097 + day_of_era - 719468
+    }
+
+    pub fn from_days(days: i64) -> Date {
+        let days = days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153; // March is 0
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Date { year, month, day }
+    }
+
+    fn days_in_month(year: i64, month: i64) -> i64 {
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        match month {
+            2 if leap => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl FromStr for Date {
+    type Err = ();
+
+    fn from_str(date: &str) -> Result<Date, ()> {
+        let parts: Vec<&str> = date.split('-').collect();
+        let (year, month, day) = match parts[..] {
+            [year, month, day] if year.len() == 4 && month.len() == 2 && day.len() == 2 => (
+                year.parse().map_err(|_| ())?,
+                month.parse().map_err(|_| ())?,
+                day.parse().map_err(|_| ())?,
+            ),
+            _ => return Err(()),
+        };
+        if !(1..=12).contains(&month) || !(1..=Date::days_in_month(year, month)).contains(&day) {
+            return Err(());
+        }
+        Ok(Date { year, month, day })
+    }
+}
+
+// The seed of the puzzle of size `number` for `date`. Each day and size gets its own.
+pub fn get_daily_seed(date: Date, number: usize) -> u64 {
+    Rng::new((date.to_days() as u64) << 8 | number as u64).next_u64()
+}
+
+// The puzzle of the day, the same for everyone who asks for the same day and size. None
+// like generate_puzzle if no grid of the size was the only solution of its rules.
+pub fn daily_puzzle(date: Date, number: usize) -> Option<Board> {
+    generate_puzzle(
+        number,
+        Symmetry::None,
+        &mut Rng::new(get_daily_seed(date, number)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_rules;
+
+    #[test]
+    fn test_daily_puzzle() {
+        let date: Date = "2000-03-01".parse().unwrap();
+        assert_eq!(date.to_days(), 11017);
+        assert_eq!(Date::from_days(11017), date);
+        assert_eq!(Date::from_days(0).to_string(), "1970-01-01");
+        for days in [-1, 59, 365, 10956, 20000, 20744] {
+            assert_eq!(Date::from_days(days).to_days(), days);
+        }
+        assert_eq!(
+            "2024-02-29".parse::<Date>().map(|date| date.to_days()),
+            Ok(19782)
+        );
+        assert!("2023-02-29".parse::<Date>().is_err());
+        assert!("2023-13-01".parse::<Date>().is_err());
+        assert!("2023-1-01".parse::<Date>().is_err());
+
+        let puzzle = daily_puzzle(date, 5).unwrap();
+        let again = daily_puzzle(date, 5).unwrap();
+        let next_day = daily_puzzle(Date::from_days(11018), 5).unwrap();
+        assert_eq!(get_rules(&puzzle), get_rules(&again));
+        assert_eq!(
+            puzzle.solve().unwrap().differences(&again.solve().unwrap()),
+            vec![]
+        );
+        assert_ne!(
+            puzzle
+                .solve()
+                .unwrap()
+                .differences(&next_day.solve().unwrap()),
+            vec![]
+        );
+        assert_ne!(get_daily_seed(date, 5), get_daily_seed(date, 6));
+    }
+}
@@ -0,0 +1,507 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::line::get_line_position;
+use crate::rng::Rng;
+use crate::{grade, Board, Grade, WhichRule};
+
+// A full grid where every row and col holds each number once, e.g. the solution a new
+// puzzle is made from. Rows and cols are 0-based like Board::cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolvedGrid {
+    number: usize,
+    values: Vec<Vec<usize>>,
+}
+
+impl SolvedGrid {
+    // A random Latin square, always the same one for the same seed of `rng`.
+    pub fn new_random(number: usize, rng: &mut Rng) -> SolvedGrid {
+        let mut grid = SolvedGrid {
+            number,
+            values: vec![vec![0; number]; number],
+        };
+        let mut used_in_rows = vec![0u32; number];
+        let mut used_in_cols = vec![0u32; number];
+        // The search tries everything, and a Latin square of any size exists.
+        assert!(grid.fill(0, &mut used_in_rows, &mut used_in_cols, rng));
+        grid
+    }
+
+    // Fills the cells from `position` on, row by row, trying the numbers in a random order
+    // and going back when a cell has none left.
+    fn fill(
+        &mut self,
+        position: usize,
+        used_in_rows: &mut [u32],
+        used_in_cols: &mut [u32],
+        rng: &mut Rng,
+    ) -> bool {
+        if position == self.number * self.number {
+            return true;
+        }
+        let (row, col) = (position / self.number, position % self.number);
+        let mut numbers: Vec<usize> = (1..=self.number)
+            .filter(|number| (used_in_rows[row] | used_in_cols[col]) & 1 << (number - 1) == 0)
+            .collect();
+        rng.shuffle(&mut numbers);
+        for number in numbers {
+            let bit = 1 << (number - 1);
+            self.values[row][col] = number;
+            used_in_rows[row] |= bit;
+            used_in_cols[col] |= bit;
+            if self.fill(position + 1, used_in_rows, used_in_cols, rng) {
+                return true;
+            }
+            used_in_rows[row] &= !bit;
+            used_in_cols[col] &= !bit;
+        }
+        self.values[row][col] = 0;
+        false
+    }
+
+    pub fn get_number(&self) -> usize {
+        self.number
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> usize {
+        self.values[row][col]
+    }
+
+    // A board with the number of every cell and no rules.
+    pub fn to_board(&self) -> Board {
+        let mut board = Board::new_board(self.number);
+        for row in 0..self.number {
+            for col in 0..self.number {
+                board.set_number(row, col, self.values[row][col]);
+            }
+        }
+        board
+    }
+
+    // How many skyscrapers are seen from the side of `which_rule` on line `index`.
+    pub fn get_rule(&self, which_rule: WhichRule, index: usize) -> usize {
+        let mut highest = 0;
+        let mut visible = 0;
+        for i in 0..self.number {
+            let (row, col) = get_line_position(self.number, which_rule, index, i);
+            if self.values[row][col] > highest {
+                highest = self.values[row][col];
+                visible += 1;
+            }
+        }
+        visible
+    }
+
+    // An empty board with all 4N rules of the grid. Other grids can have the same rules,
+    // e.g. when swapping some 1s and 2s hides nothing new behind higher skyscrapers.
+    pub fn to_puzzle(&self) -> Board {
+        let mut board = Board::new_board(self.number);
+        for which_rule in WhichRule::ALL {
+            for index in 0..self.number {
+                board.set_rule(which_rule, index, self.get_rule(which_rule, index));
+            }
+        }
+        board
+    }
+}
+
+// How the rules left in a generated puzzle are laid out around the grid. The mirrors are
+// in the horizontal axis (top and bottom swap) and in the vertical axis (left and right).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symmetry {
+    None,
+    Rotate180,
+    Rotate90,
+    MirrorHorizontal,
+    MirrorVertical,
+}
+
+impl Symmetry {
+    // Where the rule at `index` on the side of `which_rule` ends up.
+    fn map(self, number: usize, which_rule: WhichRule, index: usize) -> (WhichRule, usize) {
+        let mirrored = number - 1 - index;
+        match (self, which_rule) {
+            (Symmetry::None, _) => (which_rule, index),
+            (Symmetry::Rotate180, _) => (which_rule.opposite(), mirrored),
+            // Clockwise, so the top side goes to the right.
+            (Symmetry::Rotate90, WhichRule::Up) => (WhichRule::Right, index),
+            (Symmetry::Rotate90, WhichRule::Right) => (WhichRule::Down, mirrored),
+            (Symmetry::Rotate90, WhichRule::Down) => (WhichRule::Left, index),
+            (Symmetry::Rotate90, WhichRule::Left) => (WhichRule::Up, mirrored),
+            (Symmetry::MirrorHorizontal, WhichRule::Up | WhichRule::Down) => {
+                (which_rule.opposite(), index)
+            }
+            (Symmetry::MirrorHorizontal, _) => (which_rule, mirrored),
+            (Symmetry::MirrorVertical, WhichRule::Left | WhichRule::Right) => {
+                (which_rule.opposite(), index)
+            }
+            (Symmetry::MirrorVertical, _) => (which_rule, mirrored),
+        }
+    }
+
+    // The rules that have to go together with the one at `index` on the side of
+    // `which_rule`, that one included.
+    fn orbit(self, number: usize, which_rule: WhichRule, index: usize) -> Vec<(WhichRule, usize)> {
+        let mut orbit = vec![(which_rule, index)];
+        loop {
+            let (which_rule, index) = orbit[orbit.len() - 1];
+            let next = self.map(number, which_rule, index);
+            if orbit.contains(&next) {
+                return orbit;
+            }
+            orbit.push(next);
+        }
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Symmetry::None => "none",
+            Symmetry::Rotate180 => "180",
+            Symmetry::Rotate90 => "90",
+            Symmetry::MirrorHorizontal => "horizontal",
+            Symmetry::MirrorVertical => "vertical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Reads a symmetry as printed.
+impl FromStr for Symmetry {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Symmetry, ()> {
+        match name.to_lowercase().as_str() {
+            "none" => Ok(Symmetry::None),
+            "180" => Ok(Symmetry::Rotate180),
+            "90" => Ok(Symmetry::Rotate90),
+            "horizontal" => Ok(Symmetry::MirrorHorizontal),
+            "vertical" => Ok(Symmetry::MirrorVertical),
+            _ => Err(()),
+        }
+    }
+}
+
+// How many random grids generate_puzzle looks at before giving up. About 1 in 20 6x6
+// grids is the only solution of its rules, 1 in 200 7x7 grids and hardly any bigger one.
+const MAX_GRID_TRIES: usize = 1000;
+
+// A new puzzle of size `number`: the rules of a random grid, with as many of them taken
+// out in a random order as can be while the grid stays the only solution. The rules that
+// `symmetry` maps onto each other are taken out together. Grids that aren't the only
+// solution of all their rules are skipped, None if they all were.
+pub fn generate_puzzle(number: usize, symmetry: Symmetry, rng: &mut Rng) -> Option<Board> {
+    let grid = new_unique_grid(number, rng)?;
+    let mut board = grid.to_puzzle();
+    remove_rules(&mut board, symmetry, rng, |board| {
+        board.count_solutions(2) == 1
+    });
+    Some(board)
+}
+
+// The first random grid that is the only solution of all its rules.
+fn new_unique_grid(number: usize, rng: &mut Rng) -> Option<SolvedGrid> {
+    (0..MAX_GRID_TRIES)
+        .map(|_| SolvedGrid::new_random(number, rng))
+        .find(|grid| grid.to_puzzle().count_solutions(2) == 1)
+}
+
+// How many sets of rules generate_puzzle_with_grade cuts down before giving up, and how
+// many times it adds givens to each of them.
+const MAX_RULE_TRIES: usize = 100;
+const PLACE_TRIES: usize = 10;
+
+// A puzzle the grader gives `target`, and how many puzzles were made to find it, or how
+// many were made in vain. With as few rules as can be, most puzzles need guessing or the
+// visibility bounds, so numbers of the grid are added as givens until the grade is down
+// to `target`. As one given can make a puzzle much easier, each set of rules is tried with
+// the givens added in several random orders.
+pub fn generate_puzzle_with_grade(
+    number: usize,
+    target: Grade,
+    symmetry: Symmetry,
+    rng: &mut Rng,
+) -> Result<(Board, usize), usize> {
+    let mut tries = 0;
+    for _ in 0..MAX_RULE_TRIES {
+        let grid = new_unique_grid(number, rng).ok_or(tries)?;
+        let mut rules = grid.to_puzzle();
+        remove_rules(&mut rules, symmetry, rng, |board| {
+            board.count_solutions(2) == 1
+        });
+        for _ in 0..PLACE_TRIES {
+            tries += 1;
+            let mut board = rules.clone();
+            if place_givens(&mut board, &grid, target, rng) == Some(target) {
+                return Ok((board, tries));
+            }
+        }
+    }
+    Err(tries)
+}
+
+// Adds the numbers of `grid` as givens in random cells while the board is graded harder
+// than `target`, leaving out those that would make it easier than that. Returns the grade
+// the board ends with.
+fn place_givens(
+    board: &mut Board,
+    grid: &SolvedGrid,
+    target: Grade,
+    rng: &mut Rng,
+) -> Option<Grade> {
+    let number = board.get_number();
+    let mut positions: Vec<(usize, usize)> = (0..number)
+        .flat_map(|row| (0..number).map(move |col| (row, col)))
+        .collect();
+    rng.shuffle(&mut positions);
+    let mut placed = vec![];
+    let mut graded = grade(board).grade;
+    for position in positions {
+        if graded.is_some_and(|graded| graded <= target) {
+            break;
+        }
+        placed.push(position);
+        let placed_graded = grade(&with_givens(board, grid, &placed)).grade;
+        if placed_graded.is_none_or(|placed_graded| placed_graded >= target) {
+            graded = placed_graded;
+        } else {
+            placed.pop();
+        }
+    }
+    // Most givens leave the grade as it is, so those added before the last few are
+    // mostly not needed anymore.
+    if graded == Some(target) {
+        for i in (0..placed.len()).rev() {
+            let position = placed.remove(i);
+            if grade(&with_givens(board, grid, &placed)).grade != Some(target) {
+                placed.insert(i, position);
+            }
+        }
+    }
+    *board = with_givens(board, grid, &placed);
+    graded
+}
+
+// The board with the numbers of `grid` as givens at `positions`.
+fn with_givens(board: &Board, grid: &SolvedGrid, positions: &[(usize, usize)]) -> Board {
+    let mut board = board.clone();
+    for &(row, col) in positions {
+        board.set_given(row, col, grid.get(row, col));
+    }
+    board
+}
+
+// Takes out the rules of each orbit of `symmetry` in turn, in a random order, and puts
+// them back if the board then isn't `good` anymore.
+fn remove_rules(
+    board: &mut Board,
+    symmetry: Symmetry,
+    rng: &mut Rng,
+    good: impl Fn(&Board) -> bool,
+) {
+    let number = board.get_number();
+    let mut orbits: Vec<Vec<(WhichRule, usize)>> = vec![];
+    for which_rule in WhichRule::ALL {
+        for index in 0..number {
+            if !orbits
+                .iter()
+                .flatten()
+                .any(|&rule| rule == (which_rule, index))
+            {
+                orbits.push(symmetry.orbit(number, which_rule, index));
+            }
+        }
+    }
+    rng.shuffle(&mut orbits);
+    remove_rule_groups(board, orbits, good);
+}
+
+// Takes out each group of rules in turn, in the order given, and puts it back if the
+// board then isn't `good` anymore.
+fn remove_rule_groups(
+    board: &mut Board,
+    groups: Vec<Vec<(WhichRule, usize)>>,
+    good: impl Fn(&Board) -> bool,
+) {
+    for group in groups {
+        let rules: Vec<usize> = group
+            .iter()
+            .map(|&(which_rule, index)| board.get_rule(which_rule, index))
+            .collect();
+        for &(which_rule, index) in &group {
+            board.set_rule(which_rule, index, 0);
+        }
+        if !good(board) {
+            for (&(which_rule, index), rule) in group.iter().zip(rules) {
+                board.set_rule(which_rule, index, rule);
+            }
+        }
+    }
+}
+
+// The rules of the board that aren't 0, from the top side on.
+pub fn get_rules(board: &Board) -> Vec<(WhichRule, usize)> {
+    let mut rules = vec![];
+    for which_rule in WhichRule::ALL {
+        for index in 0..board.get_number() {
+            if board.get_rule(which_rule, index) > 0 {
+                rules.push((which_rule, index));
+            }
+        }
+    }
+    rules
+}
+
+// The board with its rules taken out one at a time, top side first, as long as it keeps
+// one solution. No rule of the result can go, though taking them out in another order
+// can leave fewer.
+pub fn minimize_rules(board: &Board) -> Board {
+    let mut board = board.clone();
+    let groups = get_rules(&board)
+        .into_iter()
+        .map(|rule| vec![rule])
+        .collect();
+    remove_rule_groups(&mut board, groups, |board| board.count_solutions(2) == 1);
+    board
+}
+
+// The rules without which the board has more than one solution. Every set of rules that
+// keeps the board unique has them.
+pub fn get_essential_rules(board: &Board) -> Vec<(WhichRule, usize)> {
+    get_rules(board)
+        .into_iter()
+        .filter(|&(which_rule, index)| {
+            let mut less = board.clone();
+            less.set_rule(which_rule, index, 0);
+            less.count_solutions(2) > 1
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_solved_grid() {
+        for number in 1..=9 {
+            let grid = SolvedGrid::new_random(number, &mut Rng::new(number as u64));
+            for i in 0..number {
+                let mut row: Vec<usize> = (0..number).map(|j| grid.get(i, j)).collect();
+                let mut col: Vec<usize> = (0..number).map(|j| grid.get(j, i)).collect();
+                row.sort();
+                col.sort();
+                assert_eq!(row, (1..=number).collect::<Vec<_>>());
+                assert_eq!(col, row);
+            }
+        }
+
+        let grid = SolvedGrid::new_random(6, &mut Rng::new(7));
+        assert_eq!(grid, SolvedGrid::new_random(6, &mut Rng::new(7)));
+        assert_ne!(grid, SolvedGrid::new_random(6, &mut Rng::new(8)));
+
+        let board = grid.to_board();
+        assert!(board.is_solved());
+        assert_eq!(board.get_cell(2, 3).value(), Some(grid.get(2, 3)));
+    }
+
+    #[test]
+    fn test_generate_puzzle() {
+        let grid = SolvedGrid::new_random(5, &mut Rng::new(3));
+        let puzzle = grid.to_puzzle();
+        assert_eq!(
+            puzzle.solve().unwrap().differences(&grid.to_board()),
+            vec![]
+        );
+
+        let puzzle = generate_puzzle(5, Symmetry::None, &mut Rng::new(3)).unwrap();
+        assert_eq!(
+            puzzle.solve().unwrap().differences(&grid.to_board()),
+            vec![]
+        );
+        assert_eq!(puzzle.count_solutions(2), 1);
+        // Every rule that is left is needed.
+        for which_rule in WhichRule::ALL {
+            for index in 0..5 {
+                if puzzle.get_rule(which_rule, index) != 0 {
+                    let mut less = puzzle.clone();
+                    less.set_rule(which_rule, index, 0);
+                    assert!(less.count_solutions(2) > 1);
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_generate_puzzle_with_grade() {
+        let (puzzle, tries) =
+            generate_puzzle_with_grade(5, Grade::Medium, Symmetry::None, &mut Rng::new(3)).unwrap();
+        assert!(tries >= 1);
+        assert_eq!(grade(&puzzle).grade, Some(Grade::Medium));
+        assert_eq!(puzzle.count_solutions(2), 1);
+        let solution = puzzle.solve().unwrap();
+        for row in 0..5 {
+            for col in 0..5 {
+                let given = puzzle.get_given(row, col);
+                if given > 0 {
+                    assert_eq!(solution.get_cell(row, col).value(), Some(given));
+                }
+            }
+        }
+
+        // A 1x1 puzzle is always Easy.
+        assert_eq!(
+            generate_puzzle_with_grade(1, Grade::Hard, Symmetry::None, &mut Rng::new(3))
+                .map(|(_, tries)| tries),
+            Err(MAX_RULE_TRIES * PLACE_TRIES)
+        );
+    }
+
+    #[test]
+    fn test_minimize_rules() {
+        let grid = SolvedGrid::new_random(5, &mut Rng::new(3));
+        let puzzle = grid.to_puzzle();
+        let essential = get_essential_rules(&puzzle);
+        let minimized = minimize_rules(&puzzle);
+        assert_eq!(minimized.count_solutions(2), 1);
+        assert!(get_rules(&minimized).len() < get_rules(&puzzle).len());
+        assert_eq!(get_essential_rules(&minimized), get_rules(&minimized));
+        assert!(essential
+            .iter()
+            .all(|rule| get_rules(&minimized).contains(rule)));
+
+        // A puzzle that is already minimal stays as it is.
+        let puzzle = generate_puzzle(5, Symmetry::None, &mut Rng::new(3)).unwrap();
+        assert_eq!(get_rules(&minimize_rules(&puzzle)), get_rules(&puzzle));
+        assert_eq!(get_essential_rules(&puzzle), get_rules(&puzzle));
+    }
+
+    #[test]
+    fn test_symmetry() {
+        assert_eq!(
+            Symmetry::Rotate90.orbit(5, WhichRule::Up, 1),
+            vec![
+                (WhichRule::Up, 1),
+                (WhichRule::Right, 1),
+                (WhichRule::Down, 3),
+                (WhichRule::Left, 3)
+            ]
+        );
+        assert_eq!(Symmetry::MirrorVertical.orbit(5, WhichRule::Up, 2).len(), 1);
+
+        for symmetry in ["none", "180", "90", "horizontal", "vertical"] {
+            let symmetry: Symmetry = symmetry.parse().unwrap();
+            let puzzle = generate_puzzle(5, symmetry, &mut Rng::new(5)).unwrap();
+            assert_eq!(puzzle.count_solutions(2), 1);
+            for which_rule in WhichRule::ALL {
+                for index in 0..5 {
+                    let (other_rule, other_index) = symmetry.map(5, which_rule, index);
+                    assert_eq!(
+                        puzzle.get_rule(which_rule, index) == 0,
+                        puzzle.get_rule(other_rule, other_index) == 0
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Board, Techniques};
+
+// How hard a puzzle is to solve by hand, from the hardest technique it needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Grade {
+    Easy,
+    Medium,
+    Hard,
+    Extreme,
+}
+
+impl Grade {
+    fn from_difficulty(difficulty: usize) -> Grade {
+        match difficulty {
+            0 | 1 => Grade::Easy,
+            2 => Grade::Medium,
+            3 => Grade::Hard,
+            _ => Grade::Extreme,
+        }
+    }
+}
+
+impl fmt::Display for Grade {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Grade::Easy => "Easy",
+            Grade::Medium => "Medium",
+            Grade::Hard => "Hard",
+            Grade::Extreme => "Extreme",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Reads a grade as printed, in any case.
+impl FromStr for Grade {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Grade, ()> {
+        match name.to_lowercase().as_str() {
+            "easy" => Ok(Grade::Easy),
+            "medium" => Ok(Grade::Medium),
+            "hard" => Ok(Grade::Hard),
+            "extreme" => Ok(Grade::Extreme),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct GradeReport {
+    // None if the techniques alone don't solve the puzzle, because it needs guessing, has
+    // more than one solution or has none.
+    pub grade: Option<Grade>,
+    // The hardest technique that was needed and its difficulty.
+    pub hardest: Option<(&'static str, usize)>,
+    // How many steps each technique made, in the default order of the techniques.
+    pub steps: Vec<(&'static str, usize)>,
+}
+
+// Solves a copy of the board one Board::step_area at a time, so every deduction is made
+// with the easiest technique that can make it. The default techniques and subset sizes
+// are used whatever the board has set, so the same puzzle always gets the same grade.
+pub fn grade(board: &Board) -> GradeReport {
+    let mut board = board.clone();
+    *board.techniques_mut() = Techniques::default();
+    board.set_max_naked_subset_size(Board::DEFAULT_MAX_SUBSET_SIZE);
+    board.set_max_hidden_subset_size(Board::DEFAULT_MAX_SUBSET_SIZE);
+    let mut report = GradeReport {
+        grade: None,
+        hardest: None,
+        steps: board
+            .techniques()
+            .enabled()
+            .iter()
+            .map(|technique| (technique.name(), 0))
+            .collect(),
+    };
+    if !board.check_integrity().is_empty() {
+        return report;
+    }
+    loop {
+        let step = board.step_area();
+        let first = match step.first() {
+            Some(first) => first,
+            None => break,
+        };
+        // Board::step_area won't empty a cell, so a number that stayed means the board is
+        // broken.
+        let stuck = step.iter().any(|deduction| {
+            !deduction.placed
+                && board
+                    .get_cell(deduction.row, deduction.col)
+                    .contains(deduction.number)
+        });
+        if stuck {
+            return report;
+        }
+        let technique = board
+            .techniques()
+            .enabled()
+            .into_iter()
+            .find(|technique| technique.name() == first.technique)
+            .unwrap();
+        if report
+            .hardest
+            .is_none_or(|(_, difficulty)| technique.difficulty() > difficulty)
+        {
+            report.hardest = Some((technique.name(), technique.difficulty()));
+        }
+        if let Some(count) = report
+            .steps
+            .iter_mut()
+            .find(|(name, _)| *name == technique.name())
+        {
+            count.1 += 1;
+        }
+    }
+    if board.is_solved() {
+        let difficulty = report.hardest.map_or(0, |(_, difficulty)| difficulty);
+        report.grade = Some(Grade::from_difficulty(difficulty));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WhichRule;
+
+    fn new_board_with_rules(n: usize, rules: [&str; 4]) -> Board {
+        let mut board = Board::new_board(n);
+        for (rules, which_rule) in rules.into_iter().zip(WhichRule::ALL) {
+            board.update_rule_x(rules, which_rule);
+        }
+        board
+    }
+
+    // How many steps the technique called `name` made.
+    fn steps_of(report: &GradeReport, name: &str) -> usize {
+        report
+            .steps
+            .iter()
+            .find(|(technique, _)| *technique == name)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn test_grade() {
+        // Every clue of a 4x4 board only needs the clues and the solved cells.
+        let easy = new_board_with_rules(4, ["4321", "1222", "4321", "1222"]);
+        let report = grade(&easy);
+        assert_eq!(report.grade, Some(Grade::Easy));
+        assert_eq!(report.hardest, Some(("rules up", 1)));
+        assert_eq!(steps_of(&report, "solved cells"), 4);
+
+        let board = new_board_with_rules(5, ["30000", "03000", "02220", "30100"]);
+        let report = grade(&board);
+        assert_eq!(report.grade, Some(Grade::Extreme));
+        assert_eq!(report.hardest, Some(("visibility bounds", 4)));
+        assert_eq!(steps_of(&report, "visibility bounds"), 3);
+        assert_eq!(steps_of(&report, "unique number left"), 7);
+
+        // The subset sizes of the board don't count either.
+        let mut subsets = new_board_with_rules(4, ["3003", "0000", "0030", "0000"]);
+        let report = grade(&subsets);
+        assert_eq!(steps_of(&report, "hidden subsets"), 1);
+        subsets.set_max_naked_subset_size(1);
+        subsets.set_max_hidden_subset_size(1);
+        assert_eq!(grade(&subsets).steps, report.steps);
+
+        let two = new_board_with_rules(5, ["22421", "23213", "41232", "12432"]);
+        assert_eq!(grade(&two).grade, None);
+        let none = new_board_with_rules(4, ["4000", "4000", "0000", "0000"]);
+        assert_eq!(grade(&none).grade, None);
+
+        assert_eq!("Hard".parse(), Ok(Grade::Hard));
+        assert_eq!("extreme".parse(), Ok(Grade::Extreme));
+        assert_eq!("mixed".parse::<Grade>(), Err(()));
+    }
+}
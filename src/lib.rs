@@ -1,8 +1,29 @@
-use std::{ io, process};
+use std::collections::HashMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{io, process};
 mod board;
+mod book;
 mod cell;
-use crate::board::{Board, WhichRule};
-use crate::cell::Cell;
+mod daily;
+mod generator;
+mod grader;
+mod line;
+mod rng;
+mod technique;
+pub use crate::board::{Board, Contradiction, Fish, NakedSubset, WhichRule};
+pub use crate::book::{
+    get_puzzle_id, make_book_puzzle, render_book, sort_book_puzzles, BookPuzzle, MIXED_GRADES,
+};
+pub use crate::cell::Cell;
+pub use crate::daily::{daily_puzzle, get_daily_seed, Date};
+pub use crate::generator::{
+    generate_puzzle, generate_puzzle_with_grade, get_essential_rules, get_rules, minimize_rules,
+    SolvedGrid, Symmetry,
+};
+pub use crate::grader::{grade, Grade, GradeReport};
+pub use crate::rng::Rng;
+pub use crate::technique::{Area, Deduction, Technique, TechniqueEntry, Techniques};
 
 pub fn run_test_7_program() {
     // Test file =
@@ -26,56 +47,91 @@ pub fn run_test_7_program() {
     parse_command("rl553022", &mut board);
     parse_command("rr0005043", &mut board);
     parse_command("rd0002325", &mut board);
-
-    loop {
-        board.print_board();
-        let mut input_text = String::new();
-        io::stdin()
-            .read_line(&mut input_text)
-            .expect("Failed to read line");
-        let command = input_text.trim();
-        parse_command(&command, &mut board);
-    }
+    run_commands(board);
 }
 
 pub fn run_program(number: usize) {
-    let mut board = Board::new_board(number);
+    run_commands(Board::new_board(number));
+}
+
+// Starts on the daily puzzle, with the same arguments as the "daily" command.
+pub fn run_daily_program(args: &str) {
+    let mut board = Board::new_board(1);
+    if daily(args, &mut board) {
+        run_commands(board);
+    }
+}
 
+fn run_commands(mut board: Board) {
     loop {
         board.print_board();
+        print_contradictions(&board.check_integrity());
         let mut input_text = String::new();
         io::stdin()
             .read_line(&mut input_text)
             .expect("Failed to read line");
         let command = input_text.trim();
-        parse_command(&command, &mut board);
+        parse_command(command, &mut board);
     }
 }
 
 fn parse_command(input_command: &str, board: &mut Board) {
     let mut command = input_command;
     let mut comm_args = "";
-    if input_command.starts_with("ru") {
+    if let Some(args) = input_command.strip_prefix("ru") {
         command = "ru";
-        comm_args = &input_command[2..];
-    } else if input_command.starts_with("rd") {
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("rd") {
         command = "rd";
-        comm_args = &input_command[2..];
-    } else if input_command.starts_with("rr") {
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("rr") {
         command = "rr";
-        comm_args = &input_command[2..];
-    } else if input_command.starts_with("rl") {
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("rl") {
         command = "rl";
-        comm_args = &input_command[2..];
-    } else if input_command.starts_with("ok") {
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("ok") {
         command = "ok";
-        comm_args = &input_command[2..];
-    } else if input_command.starts_with("restore") {
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("restore") {
         command = "restore";
-        comm_args = &input_command[7..];
-    } else if input_command.starts_with("remove") {
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("remove") {
         command = "remove";
-        comm_args = &input_command[6..];
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("complete") {
+        command = "complete";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("count") {
+        command = "count";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("naked") {
+        command = "naked";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("enable") {
+        command = "enable";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("disable") {
+        command = "disable";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("move") {
+        command = "move";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("why") {
+        command = "why";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("book") {
+        command = "book";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("daily") {
+        command = "daily";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("given") {
+        command = "given";
+        comm_args = args;
+    } else if let Some(args) = input_command.strip_prefix("generate") {
+        command = "generate";
+        comm_args = args;
     }
     match command {
         "exit" => process::exit(0),
@@ -86,11 +142,551 @@ fn parse_command(input_command: &str, board: &mut Board) {
         "rr" => board.update_rule_x(comm_args, WhichRule::Right),
         "ok" => board.check_ok_cell(comm_args),
         "remove" => board.remove_number_from_cell(comm_args),
-        "c" | "check" => board.check_all(),
+        "c" | "check" => {
+            // The contradictions themselves are shown with the board.
+            if board.check_all().is_err() {
+                println!("Nothing was checked because the board is broken");
+            }
+        }
+        "complete" => complete(comm_args, board),
+        "count" => count(comm_args, board),
+        "naked" => naked(comm_args, board),
+        "fish" => {
+            let fish = board.remove_fish();
+            println!("Found {} fish", fish.len());
+            for fish in fish {
+                println!("  {}", fish);
+            }
+        }
+        "solve" => match board.solve() {
+            Some(solution) => solution.print_board(),
+            None => println!("This board has no solution"),
+        },
         "restore" => board.restore_cell(comm_args),
+        "techniques" => print_techniques(board.techniques()),
+        "enable" => enable_technique(comm_args, board, true),
+        "disable" => enable_technique(comm_args, board, false),
+        "move" => move_technique(comm_args, board),
+        "why" => why(comm_args, board),
+        "step" => step(board),
+        "hint" => hint(board),
+        "grade" => print_grade(board),
+        "generate" => generate(comm_args, board),
+        "given" => given(comm_args, board),
+        "minimize" => minimize(board),
+        "book" => book(comm_args),
+        "daily" => {
+            daily(comm_args, board);
+        }
         _ => println!(
             "\"{}\" is not a valid command, type \"help\" to get valid commands",
             input_command
         ),
     }
-}
\ No newline at end of file
+}
+
+fn complete(args: &str, board: &mut Board) {
+    let args = args.trim();
+    let depth = if args.is_empty() {
+        None
+    } else if let Ok(depth) = args.parse() {
+        Some(depth)
+    } else {
+        println!("\"{}\" is not a valid depth for complete", args);
+        return;
+    };
+    let report = board.complete(depth);
+    println!("Ran {} rounds of checks", report.rounds);
+    for (name, removed) in report.removed {
+        println!("  {}: removed {} candidates", name, removed);
+    }
+    if !report.contradictions.is_empty() {
+        println!("Stopped because the board is broken");
+    }
+}
+
+// Runs the naked subsets pass on its own, optionally changing how big the subsets can be
+// first, and shows what it found.
+fn naked(args: &str, board: &mut Board) {
+    let args = args.trim();
+    if !args.is_empty() {
+        match args.parse() {
+            Ok(size) if size >= 2 => board.set_max_naked_subset_size(size),
+            _ => {
+                println!(
+                    "\"{}\" is not a valid subset size for naked, it has to be 2 or more",
+                    args
+                );
+                return;
+            }
+        }
+    }
+    let subsets = board.remove_naked_subsets();
+    println!(
+        "Found {} naked subsets of up to {} cells",
+        subsets.len(),
+        board.max_naked_subset_size()
+    );
+    for subset in subsets {
+        println!("  {}", subset);
+    }
+}
+
+fn print_techniques(techniques: &Techniques) {
+    for (i, entry) in techniques.entries().iter().enumerate() {
+        println!(
+            "{:>2}. {} (difficulty {}){}",
+            i + 1,
+            entry.technique.name(),
+            entry.technique.difficulty(),
+            if entry.enabled { "" } else { " - disabled" }
+        );
+    }
+}
+
+// Takes the name or the position of a technique, as shown by "techniques".
+fn enable_technique(args: &str, board: &mut Board, enabled: bool) {
+    let args = args.trim();
+    match board.techniques().find(args) {
+        Some(index) => board.techniques_mut().set_enabled(index, enabled),
+        None => println!("\"{}\" is not a technique", args),
+    }
+}
+
+// "move <from> <to>" with the positions shown by "techniques", e.g. "move 12 1" runs the
+// line permutations first.
+fn move_technique(args: &str, board: &mut Board) {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let positions = match args[..] {
+        [from, to] => board
+            .techniques()
+            .find(from)
+            .zip(board.techniques().find(to)),
+        _ => None,
+    };
+    match positions {
+        Some((from, to)) => board.techniques_mut().move_to(from, to),
+        None => println!("Use \"move <from> <to>\" with positions shown by \"techniques\""),
+    }
+}
+
+// "why <row><col><number>", e.g. "why 237" tells why 7 is gone from cell 23, or why it is
+// the only number left there.
+fn why(args: &str, board: &Board) {
+    let args = args.trim();
+    let digits: Vec<usize> = args
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|digit| digit as usize)
+        .collect();
+    let (row, col, number) = match digits[..] {
+        [row, col, number] if args.len() == 3 => (row, col, number),
+        _ => {
+            println!("Use \"why <row><col><number>\", e.g. \"why 237\"");
+            return;
+        }
+    };
+    let size = board.get_number();
+    if !(1..=size).contains(&row) || !(1..=size).contains(&col) || !(1..=size).contains(&number) {
+        println!("\"{}\" is not on the board", args);
+        return;
+    }
+    let cell = board.get_cell(row - 1, col - 1);
+    match board.get_deduction(row - 1, col - 1, number) {
+        Some(deduction) => println!("{} ({})", deduction, deduction.technique),
+        None if cell.contains(number) && cell.value().is_none() => {
+            println!("{} can still be in cell {}{}", number, row, col)
+        }
+        None => println!(
+            "Nothing was recorded about {} in cell {}{}",
+            number, row, col
+        ),
+    }
+}
+
+// Shows which rules of the puzzle can go together and which can't go at all. What was
+// found out about the cells doesn't count, only the rules and givens.
+fn minimize(board: &Board) {
+    let puzzle = board.to_puzzle();
+    if puzzle.count_solutions(2) != 1 {
+        println!("Only a puzzle with one solution can be minimized");
+        return;
+    }
+    let minimized = minimize_rules(&puzzle);
+    println!(
+        "Kept {} of the {} rules:",
+        get_rules(&minimized).len(),
+        get_rules(&puzzle).len()
+    );
+    minimized.print_board();
+    print_puzzle_commands(&minimized);
+
+    let essential = get_essential_rules(&puzzle);
+    if essential.is_empty() {
+        println!("Every rule can go, as long as others stay");
+        return;
+    }
+    println!("Rules the puzzle can't do without:");
+    for (which_rule, index) in essential {
+        let area = Area::Line {
+            which_rule,
+            index,
+            near_rule: puzzle.get_rule(which_rule, index),
+            far_rule: 0,
+        };
+        println!("  {}", area);
+    }
+}
+
+// "given <row><col><number>" makes the number part of the puzzle, e.g. "given 234" puts a
+// given 4 in cell 23. Number 0 takes the given out again.
+fn given(args: &str, board: &mut Board) {
+    let args = args.trim();
+    let digits: Vec<usize> = args
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|digit| digit as usize)
+        .collect();
+    let size = board.get_number();
+    match digits[..] {
+        [row, col, number]
+            if args.len() == 3
+                && (1..=size).contains(&row)
+                && (1..=size).contains(&col)
+                && number <= size =>
+        {
+            board.set_given(row - 1, col - 1, number)
+        }
+        _ => println!(
+            "Use \"given <row><col><number>\" with a number up to {}, e.g. \"given 234\"",
+            size
+        ),
+    }
+}
+
+fn step(board: &mut Board) {
+    if !board.check_integrity().is_empty() {
+        println!("Nothing was checked because the board is broken");
+        return;
+    }
+    let deductions = board.step();
+    match deductions.first() {
+        Some(first) => println!("{}:", first.technique),
+        None => println!("No technique can make progress"),
+    }
+    for deduction in deductions {
+        println!("  {}", deduction);
+    }
+}
+
+// Each "hint" tells a bit more about the next step: where to look, then the technique,
+// then what it removes. "step" makes it.
+fn hint(board: &mut Board) {
+    if !board.check_integrity().is_empty() {
+        println!("No hint because the board is broken");
+        return;
+    }
+    match board.hint() {
+        Some((1, step)) => println!("Look at {}", step[0].area.location()),
+        Some((2, step)) => println!("Use {} on {}", step[0].technique, step[0].area),
+        Some((_, step)) => {
+            for deduction in step {
+                println!("{}", deduction);
+            }
+        }
+        None => println!("No technique can make progress"),
+    }
+}
+
+fn print_grade(board: &Board) {
+    let report = grade(board);
+    match report.grade {
+        Some(grade) => println!("{}", grade),
+        None => println!("This board can't be solved by the techniques alone"),
+    }
+    if let Some((name, difficulty)) = report.hardest {
+        println!("Hardest technique: {} (difficulty {})", name, difficulty);
+    }
+    println!("Steps made by each technique:");
+    for (name, steps) in report.steps {
+        if steps > 0 {
+            println!("  {}: {}", name, steps);
+        }
+    }
+}
+
+// "generate N [--seed S] [--difficulty D] [--symmetry Y]" replaces the board with a new
+// NxN puzzle, one the grader gives D if one is found, which can come with some givens,
+// with its rules laid out with the symmetry Y. Without a seed one is made up from
+// the clock, and printed so the puzzle can be made again. Bigger grids are hardly ever the
+// only solution of their rules, so N only goes up to 7.
+fn generate(args: &str, board: &mut Board) {
+    let options = ["seed", "difficulty", "symmetry"];
+    let parsed = parse_options(args, &options).and_then(|(plain, options)| {
+        let number = match plain[..] {
+            [number] => number
+                .parse()
+                .ok()
+                .filter(|number| (1..=7).contains(number))?,
+            _ => return None,
+        };
+        let seed = match options.get("seed") {
+            Some(seed) => seed.parse().ok()?,
+            None => new_seed(),
+        };
+        let target: Option<Grade> = match options.get("difficulty") {
+            Some(target) => Some(target.parse().ok()?),
+            None => None,
+        };
+        let symmetry = match options.get("symmetry") {
+            Some(symmetry) => symmetry.parse().ok()?,
+            None => Symmetry::None,
+        };
+        Some((number, seed, target, symmetry))
+    });
+    let (number, seed, target, symmetry) = match parsed {
+        Some(parsed) => parsed,
+        None => {
+            println!(
+                "Use \"generate N [--seed S] [--difficulty D] [--symmetry Y]\" with N from 1 to \
+                 7, D one of easy, medium, hard or extreme and Y one of none, 180, 90, horizontal \
+                 or vertical"
+            );
+            return;
+        }
+    };
+    let mut rng = Rng::new(seed);
+    match target {
+        Some(target) => match generate_puzzle_with_grade(number, target, symmetry, &mut rng) {
+            Ok((puzzle, tries)) => {
+                *board = puzzle;
+                println!(
+                    "Generated a {0}x{0} {1} puzzle with seed {2} on try {3}",
+                    number, target, seed, tries
+                );
+            }
+            Err(tries) => {
+                println!(
+                    "No {0}x{0} {1} puzzle was found with seed {2} after {3} tries",
+                    number, target, seed, tries
+                );
+                return;
+            }
+        },
+        None => match generate_puzzle(number, symmetry, &mut rng) {
+            Some(puzzle) => {
+                *board = puzzle;
+                println!("Generated a {0}x{0} puzzle with seed {1}", number, seed);
+            }
+            None => {
+                println!("No {0}x{0} puzzle was found with seed {1}", number, seed);
+                return;
+            }
+        },
+    }
+    print_puzzle_commands(board);
+}
+
+// Splits e.g. "6 --seed 42" into the plain arguments and the value of each option, as
+// long as every option is one of `names` and has a value.
+fn parse_options<'a>(
+    args: &'a str,
+    names: &[&str],
+) -> Option<(Vec<&'a str>, HashMap<&'a str, &'a str>)> {
+    let mut plain = vec![];
+    let mut options = HashMap::new();
+    let mut args = args.split_whitespace();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) if names.contains(&name) => {
+                options.insert(name, args.next()?);
+            }
+            Some(_) => return None,
+            None => plain.push(arg),
+        }
+    }
+    Some((plain, options))
+}
+
+// "daily [YYYY-MM-DD] [N]" replaces the board with the NxN puzzle of the day, today in
+// UTC and 6x6 unless given, and grades it. Returns false if the arguments are wrong.
+fn daily(args: &str, board: &mut Board) -> bool {
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let (date, number) = match args[..] {
+        [] => (Some(Date::today()), Some(6)),
+        [date] if date.contains('-') => (date.parse().ok(), Some(6)),
+        [number] => (Some(Date::today()), number.parse().ok()),
+        [date, number] => (date.parse().ok(), number.parse().ok()),
+        _ => (None, None),
+    };
+    let (date, number) = match (date, number) {
+        (Some(date), Some(number @ 1..=7)) => (date, number),
+        _ => {
+            println!("Use \"daily [YYYY-MM-DD] [N]\" with N from 1 to 7");
+            return false;
+        }
+    };
+    *board = match daily_puzzle(date, number) {
+        Some(puzzle) => puzzle,
+        None => {
+            println!("No {0}x{0} puzzle was found for {1}", number, date);
+            return false;
+        }
+    };
+    match grade(board).grade {
+        Some(grade) => println!("{0}x{0} puzzle of {1}, {2}", number, date, grade),
+        None => println!(
+            "{0}x{0} puzzle of {1}, too hard for the techniques alone",
+            number, date
+        ),
+    }
+    print_puzzle_commands(board);
+    true
+}
+
+// "book [--count C] [--sizes 5,6,7] [--difficulty D] [--seed S] [--out FILE]" writes C
+// puzzles to FILE, easiest first, with their solutions at the end. With D "mixed" the
+// puzzles go through every grade in turn, as they go through the sizes. By default it is
+// 10 mixed 6x6 puzzles in book.txt.
+fn book(args: &str) {
+    let options = ["count", "sizes", "difficulty", "seed", "out"];
+    let parsed = parse_options(args, &options).and_then(|(plain, options)| {
+        if !plain.is_empty() {
+            return None;
+        }
+        let count = match options.get("count") {
+            Some(count) => count.parse().ok().filter(|&count| count > 0)?,
+            None => 10,
+        };
+        let sizes: Vec<usize> = match options.get("sizes") {
+            Some(sizes) => sizes
+                .split(',')
+                .map(|size| size.parse().ok().filter(|size| (1..=7).contains(size)))
+                .collect::<Option<_>>()?,
+            None => vec![6],
+        };
+        let targets = match options.get("difficulty") {
+            Some(&"mixed") | None => MIXED_GRADES.to_vec(),
+            Some(target) => vec![target.parse().ok()?],
+        };
+        let seed = match options.get("seed") {
+            Some(seed) => seed.parse().ok()?,
+            None => new_seed(),
+        };
+        let out = options.get("out").copied().unwrap_or("book.txt");
+        Some((count, sizes, targets, seed, out))
+    });
+    let (count, sizes, targets, seed, out) = match parsed {
+        Some(parsed) => parsed,
+        None => {
+            println!(
+                "Use \"book [--count C] [--sizes 5,6,7] [--difficulty D] [--seed S] [--out FILE]\" \
+                 with sizes from 1 to 7 and D one of easy, medium, hard, extreme or mixed"
+            );
+            return;
+        }
+    };
+
+    let mut rng = Rng::new(seed);
+    let mut puzzles = vec![];
+    for i in 0..count {
+        let number = sizes[i % sizes.len()];
+        let target = targets[i / sizes.len() % targets.len()];
+        let puzzle = match make_book_puzzle(number, Some(target), &mut rng) {
+            Some(puzzle) => puzzle,
+            None => {
+                println!("No {0}x{0} puzzle was found, leaving it out", number);
+                continue;
+            }
+        };
+        if puzzle.grade != Some(target) {
+            println!(
+                "No {0}x{0} {1} puzzle was found, using another one",
+                number, target
+            );
+        }
+        println!("Made puzzle {} of {} ({})", i + 1, count, puzzle.id);
+        puzzles.push(puzzle);
+    }
+    sort_book_puzzles(&mut puzzles);
+    match fs::write(out, render_book(&puzzles, seed)) {
+        Ok(()) => println!("Wrote {} puzzles to {}", puzzles.len(), out),
+        Err(error) => println!("Couldn't write {}: {}", out, error),
+    }
+}
+
+fn new_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+// The commands that enter the rules and givens of the board again.
+fn print_puzzle_commands(board: &Board) {
+    let mut commands: Vec<String> = [
+        ("ru", WhichRule::Up),
+        ("rd", WhichRule::Down),
+        ("rl", WhichRule::Left),
+        ("rr", WhichRule::Right),
+    ]
+    .iter()
+    .map(|&(command, which_rule)| {
+        let rules: String = (0..board.get_number())
+            .map(|index| board.get_rule(which_rule, index).to_string())
+            .collect();
+        format!("{}{}", command, rules)
+    })
+    .collect();
+    for row in 0..board.get_number() {
+        for col in 0..board.get_number() {
+            let given = board.get_given(row, col);
+            if given > 0 {
+                commands.push(format!("given {}{}{}", row + 1, col + 1, given));
+            }
+        }
+    }
+    println!("{}", commands.join(" "));
+}
+
+fn print_contradictions(contradictions: &[Contradiction]) {
+    for contradiction in contradictions {
+        println!("Contradiction: {}", contradiction);
+    }
+}
+
+fn count(args: &str, board: &Board) {
+    let args = args.trim();
+    let limit = if args.is_empty() {
+        100
+    } else if let Ok(limit) = args.parse() {
+        limit
+    } else {
+        println!("\"{}\" is not a valid limit for count", args);
+        return;
+    };
+    let limit = limit.max(2);
+    match board.count_solutions(limit) {
+        0 => println!("This board has no solution"),
+        1 => {
+            println!("This board has exactly one solution");
+            board.solve().unwrap().print_board();
+        }
+        found => {
+            if found >= limit {
+                println!("This board has at least {} solutions", limit);
+            } else {
+                println!("This board has {} solutions", found);
+            }
+            let solutions = board.find_solutions(2);
+            println!("Two of them:");
+            solutions[0].print_board();
+            solutions[1].print_board();
+            let cells: Vec<String> = solutions[0]
+                .differences(&solutions[1])
+                .iter()
+                .map(|(row, col)| format!("{}{}", row + 1, col + 1))
+                .collect();
+            println!("They differ in cells {}", cells.join(", "));
+        }
+    }
+}
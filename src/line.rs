@@ -0,0 +1,91 @@
+use std::ops::{Index, IndexMut};
+
+use crate::board::WhichRule;
+use crate::Cell;
+
+// The cells of a row or column in order. The line checks take any Line, so they work the
+// same on a Vec<Cell> in the tests and straight on Board::cells through a LineView.
+pub trait Line: IndexMut<usize, Output = Cell> {
+    fn len(&self) -> usize;
+
+    // The (row, col) of the i-th cell on the board, used to report what a check did. A
+    // plain list of cells is taken to be row 0.
+    fn position(&self, i: usize) -> (usize, usize) {
+        (0, i)
+    }
+
+    fn to_vec(&self) -> Vec<Cell> {
+        (0..self.len()).map(|i| self[i]).collect()
+    }
+}
+
+impl Line for [Cell] {
+    fn len(&self) -> usize {
+        <[Cell]>::len(self)
+    }
+}
+
+impl Line for Vec<Cell> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+// The (row, col) of the i-th cell of a line, counting from the side of `which_rule`.
+pub fn get_line_position(
+    number: usize,
+    which_rule: WhichRule,
+    index: usize,
+    i: usize,
+) -> (usize, usize) {
+    match which_rule {
+        WhichRule::Up => (i, index),
+        WhichRule::Down => (number - 1 - i, index),
+        WhichRule::Left => (index, i),
+        WhichRule::Right => (index, number - 1 - i),
+    }
+}
+
+// A row or column of the board read from the side of `which_rule`, so line[0] is the cell
+// next to that rule. Changes go straight to the board.
+pub struct LineView<'a> {
+    cells: &'a mut [Vec<Cell>],
+    which_rule: WhichRule,
+    index: usize,
+}
+
+impl<'a> LineView<'a> {
+    pub fn new(cells: &'a mut [Vec<Cell>], which_rule: WhichRule, index: usize) -> LineView<'a> {
+        LineView {
+            cells,
+            which_rule,
+            index,
+        }
+    }
+}
+
+impl Index<usize> for LineView<'_> {
+    type Output = Cell;
+
+    fn index(&self, i: usize) -> &Cell {
+        let (row, col) = self.position(i);
+        &self.cells[row][col]
+    }
+}
+
+impl IndexMut<usize> for LineView<'_> {
+    fn index_mut(&mut self, i: usize) -> &mut Cell {
+        let (row, col) = self.position(i);
+        &mut self.cells[row][col]
+    }
+}
+
+impl Line for LineView<'_> {
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn position(&self, i: usize) -> (usize, usize) {
+        get_line_position(self.cells.len(), self.which_rule, self.index, i)
+    }
+}
@@ -9,13 +9,15 @@ fn main() {
         Some(arg) => {
             if arg.eq("test7") {
                 skyscrapers::run_test_7_program();
+            } else if arg.eq("daily") {
+                skyscrapers::run_daily_program(&args[2..].join(" "));
             } else {
                 skyscrapers::run_program(arg.parse().unwrap());
             }
         }
         None => {
             println!("provide a number N to specify the NxN grid of skyscrapers!");
-            return;
+            println!("or \"daily [YYYY-MM-DD] [N]\" to play the puzzle of the day");
         }
     }
-}
\ No newline at end of file
+}
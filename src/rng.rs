@@ -0,0 +1,70 @@
+// A small seedable random number generator (SplitMix64), so generated puzzles only depend
+// on their seed and not on a crate or the platform.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // A number in 0..bound, every one as likely. Panics if bound is 0.
+    pub fn below(&mut self, bound: usize) -> usize {
+        assert!(bound > 0, "can't pick a number below 0");
+        let bound = bound as u64;
+        // Values past the last whole multiple of bound would make the low numbers more
+        // likely, so they are drawn again.
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return (value % bound) as usize;
+            }
+        }
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng() {
+        let mut first = Rng::new(42);
+        let mut second = Rng::new(42);
+        let numbers: Vec<u64> = (0..10).map(|_| first.next_u64()).collect();
+        assert_eq!(
+            numbers,
+            (0..10).map(|_| second.next_u64()).collect::<Vec<_>>()
+        );
+        assert_ne!(Rng::new(43).next_u64(), numbers[0]);
+
+        let mut seen = [0; 6];
+        for _ in 0..600 {
+            seen[first.below(6)] += 1;
+        }
+        assert!(seen.iter().all(|&count| count > 50));
+
+        let mut items: Vec<usize> = (0..20).collect();
+        first.shuffle(&mut items);
+        assert_ne!(items, (0..20).collect::<Vec<_>>());
+        items.sort();
+        assert_eq!(items, (0..20).collect::<Vec<_>>());
+    }
+}
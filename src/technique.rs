@@ -0,0 +1,444 @@
+use std::fmt;
+
+use crate::board::get_fish_name;
+use crate::{Board, WhichRule};
+
+// What a deduction was based on. Rows, cols and rules are 0-based like Board::cells,
+// Display shows them 1-based like the commands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    // A line read from the side of `which_rule`, with the clues at both ends that were
+    // used (0 if not).
+    Line {
+        which_rule: WhichRule,
+        index: usize,
+        near_rule: usize,
+        far_rule: usize,
+    },
+    // A solved cell.
+    Cell {
+        row: usize,
+        col: usize,
+        number: usize,
+    },
+    // Cells of the row (or col) `line` that only hold the numbers between them, with bit i
+    // of `cells` set for the i-th cell and bit (x - 1) of `numbers` for x.
+    Subset {
+        in_rows: bool,
+        line: usize,
+        cells: u32,
+        numbers: u32,
+    },
+    // A fish of `number` over the rows (or cols) set in `lines`.
+    Fish {
+        number: usize,
+        in_rows: bool,
+        lines: u32,
+    },
+}
+
+impl fmt::Display for Area {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Area::Line {
+                which_rule,
+                index,
+                near_rule,
+                far_rule,
+            } => {
+                write!(f, "{} {}", which_rule.line_name(), index + 1)?;
+                let far_side = which_rule.opposite();
+                match (near_rule, far_rule) {
+                    (0, 0) => Ok(()),
+                    (rule, 0) => write!(f, ", clue {} from {}", rule, which_rule),
+                    (0, rule) => write!(f, ", clue {} from {}", rule, far_side),
+                    (near_rule, far_rule) => write!(
+                        f,
+                        ", clues {} from {} and {} from {}",
+                        near_rule, which_rule, far_rule, far_side
+                    ),
+                }
+            }
+            Area::Cell { row, col, number } => {
+                write!(f, "cell {}{} is {}", row + 1, col + 1, number)
+            }
+            Area::Subset {
+                in_rows,
+                line,
+                cells,
+                numbers,
+            } => {
+                let cells: Vec<String> = get_bits(cells)
+                    .map(|i| {
+                        let (row, col) = if in_rows { (line, i) } else { (i, line) };
+                        format!("{}{}", row + 1, col + 1)
+                    })
+                    .collect();
+                let numbers: Vec<String> = get_bits(numbers).map(|i| (i + 1).to_string()).collect();
+                write!(
+                    f,
+                    "cells {} only hold {}",
+                    cells.join(", "),
+                    numbers.join(", ")
+                )
+            }
+            Area::Fish {
+                number,
+                in_rows,
+                lines,
+            } => write!(
+                f,
+                "{} of {} in {}",
+                get_fish_name(lines.count_ones() as usize),
+                number,
+                get_fish_lines(in_rows, lines)
+            ),
+        }
+    }
+}
+
+// The positions of the bits set in `mask`, lowest first.
+fn get_bits(mask: u32) -> impl Iterator<Item = usize> {
+    (0..32).filter(move |i| mask & 1 << i != 0)
+}
+
+impl Area {
+    // Where to look for the deductions of this area, without saying what they are, e.g.
+    // "col 3" or "the 1s in rows 1, 3".
+    pub fn location(&self) -> String {
+        match *self {
+            Area::Line {
+                which_rule, index, ..
+            } => format!("{} {}", which_rule.line_name(), index + 1),
+            Area::Cell { row, col, .. } => format!("cell {}{}", row + 1, col + 1),
+            Area::Subset { in_rows, line, .. } => {
+                format!("{} {}", if in_rows { "row" } else { "col" }, line + 1)
+            }
+            Area::Fish {
+                number,
+                in_rows,
+                lines,
+            } => format!("the {}s in {}", number, get_fish_lines(in_rows, lines)),
+        }
+    }
+}
+
+fn get_fish_lines(in_rows: bool, lines: u32) -> String {
+    let lines: Vec<String> = get_bits(lines).map(|i| (i + 1).to_string()).collect();
+    format!(
+        "{} {}",
+        if in_rows { "rows" } else { "cols" },
+        lines.join(", ")
+    )
+}
+
+// A candidate a technique took out of a cell, or a cell it left with one number (placed).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Deduction {
+    pub technique: &'static str,
+    pub area: Area,
+    pub row: usize,
+    pub col: usize,
+    pub number: usize,
+    pub placed: bool,
+}
+
+impl fmt::Display for Deduction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let place = match self.area {
+            Area::Line {
+                which_rule: WhichRule::Up | WhichRule::Down,
+                ..
+            } => format!("row {}", self.row + 1),
+            Area::Line { .. } => format!("col {}", self.col + 1),
+            _ => format!("cell {}{}", self.row + 1, self.col + 1),
+        };
+        let verb = if self.placed { "must" } else { "cannot" };
+        write!(f, "{}: {} {} be in {}", self.area, self.number, verb, place)
+    }
+}
+
+// One kind of deduction. Techniques are walked in order by Board::check_all, and the
+// difficulty says how hard a deduction is for someone solving by hand, 1 being the easiest.
+pub trait Technique: Sync {
+    fn name(&self) -> &'static str;
+
+    fn difficulty(&self) -> usize;
+
+    // Runs the technique over the whole board once and returns what it did.
+    fn apply(&self, board: &mut Board) -> Vec<Deduction>;
+}
+
+impl fmt::Debug for dyn Technique {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// A technique made of one of the passes of Board.
+pub struct Pass {
+    name: &'static str,
+    difficulty: usize,
+    run: fn(&mut Board) -> Vec<Deduction>,
+}
+
+impl Technique for Pass {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    fn apply(&self, board: &mut Board) -> Vec<Deduction> {
+        let mut deductions = (self.run)(board);
+        for deduction in &mut deductions {
+            deduction.technique = self.name;
+        }
+        deductions
+    }
+}
+
+static PASSES: [Pass; 12] = [
+    Pass {
+        name: "rules up",
+        difficulty: 1,
+        run: |board| board.check_line(WhichRule::Up),
+    },
+    Pass {
+        name: "rules down",
+        difficulty: 1,
+        run: |board| board.check_line(WhichRule::Down),
+    },
+    Pass {
+        name: "rules left",
+        difficulty: 1,
+        run: |board| board.check_line(WhichRule::Left),
+    },
+    Pass {
+        name: "rules right",
+        difficulty: 1,
+        run: |board| board.check_line(WhichRule::Right),
+    },
+    Pass {
+        name: "unique number left",
+        difficulty: 2,
+        run: Board::check_unique_number_left_all_cells,
+    },
+    Pass {
+        name: "hidden subsets",
+        difficulty: 3,
+        run: Board::check_hidden_subsets_all_cells,
+    },
+    Pass {
+        name: "naked subsets",
+        difficulty: 3,
+        run: Board::check_naked_subsets_all_cells,
+    },
+    Pass {
+        name: "fish",
+        difficulty: 4,
+        run: Board::check_fish_all_cells,
+    },
+    Pass {
+        name: "solved cells",
+        difficulty: 1,
+        run: Board::check_compl_cell_excl_hor_and_ver,
+    },
+    Pass {
+        name: "opposite rules",
+        difficulty: 2,
+        run: Board::check_opposite_rules_all_cells,
+    },
+    Pass {
+        name: "visibility bounds",
+        difficulty: 4,
+        run: Board::check_visibility_bounds_all_cells,
+    },
+    Pass {
+        name: "line permutations",
+        difficulty: 5,
+        run: Board::check_line_permutations_all_cells,
+    },
+];
+
+// Runs every pass once in the default order, without looking at what they removed.
+pub(crate) fn run_default_passes(board: &mut Board) {
+    for pass in &PASSES {
+        (pass.run)(board);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TechniqueEntry {
+    pub technique: &'static dyn Technique,
+    pub enabled: bool,
+}
+
+// The techniques Board::check_all runs, in order. Every board starts with the passes of
+// board.rs, and more can be added with Techniques::add.
+#[derive(Debug, Clone)]
+pub struct Techniques {
+    entries: Vec<TechniqueEntry>,
+}
+
+impl Default for Techniques {
+    fn default() -> Techniques {
+        let mut techniques = Techniques { entries: vec![] };
+        for pass in &PASSES {
+            techniques.add(pass);
+        }
+        techniques
+    }
+}
+
+impl Techniques {
+    pub fn add(&mut self, technique: &'static dyn Technique) {
+        self.entries.push(TechniqueEntry {
+            technique,
+            enabled: true,
+        });
+    }
+
+    pub fn entries(&self) -> &[TechniqueEntry] {
+        &self.entries
+    }
+
+    pub fn enabled(&self) -> Vec<&'static dyn Technique> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.technique)
+            .collect()
+    }
+
+    // Finds a technique by its name or by its 1-based position in the list.
+    pub fn find(&self, name_or_position: &str) -> Option<usize> {
+        if let Ok(position) = name_or_position.parse::<usize>() {
+            return (1..=self.entries.len())
+                .contains(&position)
+                .then(|| position - 1);
+        }
+        self.entries
+            .iter()
+            .position(|entry| entry.technique.name() == name_or_position)
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.entries[index].enabled = enabled;
+    }
+
+    // Moves the technique at `from` so it ends up at `to`, shifting the ones in between.
+    pub fn move_to(&mut self, from: usize, to: usize) {
+        let entry = self.entries.remove(from);
+        self.entries.insert(to.min(self.entries.len()), entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A technique from outside board.rs: a 1 clue puts the highest number next to it, so
+    // nothing else can be there.
+    struct OneClue;
+
+    impl Technique for OneClue {
+        fn name(&self) -> &'static str {
+            "one clue"
+        }
+
+        fn difficulty(&self) -> usize {
+            1
+        }
+
+        fn apply(&self, board: &mut Board) -> Vec<Deduction> {
+            let mut deductions = vec![];
+            let number = board.get_number();
+            for col in 0..number {
+                if board.get_rule(WhichRule::Up, col) != 1 {
+                    continue;
+                }
+                let area = Area::Line {
+                    which_rule: WhichRule::Up,
+                    index: col,
+                    near_rule: 1,
+                    far_rule: 0,
+                };
+                for other in 1..number {
+                    if board.remove_candidate(0, col, other) {
+                        deductions.push(Deduction {
+                            technique: self.name(),
+                            area,
+                            row: 0,
+                            col,
+                            number: other,
+                            placed: false,
+                        });
+                    }
+                }
+            }
+            deductions
+        }
+    }
+
+    #[test]
+    fn test_techniques() {
+        let mut techniques = Techniques::default();
+        let names = |techniques: &Techniques| -> Vec<&str> {
+            techniques
+                .enabled()
+                .iter()
+                .map(|technique| technique.name())
+                .collect()
+        };
+        let fish = techniques.find("fish").unwrap();
+        assert_eq!(techniques.entries()[fish].technique.name(), "fish");
+        assert_eq!(techniques.find(&(fish + 1).to_string()), Some(fish));
+        assert_eq!(techniques.find("0"), None);
+        assert_eq!(techniques.find("guessing"), None);
+
+        techniques.move_to(techniques.find("line permutations").unwrap(), 0);
+        assert_eq!(names(&techniques)[..2], ["line permutations", "rules up"]);
+        techniques.set_enabled(0, false);
+        assert_eq!(names(&techniques)[0], "rules up");
+        assert_eq!(names(&techniques).len(), PASSES.len() - 1);
+
+        // A board with no techniques only checks itself.
+        let mut board = Board::new_board(4);
+        board.update_rule_x("1000", WhichRule::Up);
+        for index in 0..board.techniques().entries().len() {
+            board.techniques_mut().set_enabled(index, false);
+        }
+        assert_eq!(board.check_all(), Ok(vec![]));
+
+        board.techniques_mut().add(&OneClue);
+        assert_eq!(board.check_all(), Ok(vec![("one clue", 3)]));
+        assert_eq!(board.get_cell(0, 0).value(), Some(4));
+        let removed = board.get_deduction(0, 0, 2).unwrap();
+        assert_eq!(removed.technique, "one clue");
+        assert_eq!(
+            removed.to_string(),
+            "col 1, clue 1 from top: 2 cannot be in row 1"
+        );
+        assert_eq!(board.check_all(), Ok(vec![("one clue", 0)]));
+    }
+
+    #[test]
+    fn test_solver_ignores_techniques() {
+        // The solver runs its own passes, so turning every technique off doesn't change
+        // what it finds.
+        let mut board = Board::new_board(5);
+        board.update_rule_x("30000", WhichRule::Up);
+        board.update_rule_x("03000", WhichRule::Down);
+        board.update_rule_x("02220", WhichRule::Left);
+        board.update_rule_x("30100", WhichRule::Right);
+        let solution = board.solve().unwrap();
+        for index in 0..board.techniques().entries().len() {
+            board.techniques_mut().set_enabled(index, false);
+        }
+        assert_eq!(board.count_solutions(10), 1);
+        assert!(board.solve().unwrap().differences(&solution).is_empty());
+    }
+}